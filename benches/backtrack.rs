@@ -0,0 +1,47 @@
+//! Measures the parsers on texts where they often backtrack. Run with 'cargo bench'.
+extern crate quint;
+use quint::machine::*;
+use quint::node::*;
+use quint::parse::*;
+use quint::*;
+use std::time::Instant;
+
+fn measure(name: &str, iterations: u32, mut run: impl FnMut() -> bool) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        assert!(run(), "{}", name);
+    }
+    println!("{:<24}{:>16?}", name, start.elapsed() / iterations);
+}
+
+fn main() {
+    let item = r#"{ "boba": [1, -2.5e3, true, null], "fett": "jango\n" }"#;
+    let text = format!("[{}]", vec![item; 1000].join(", "));
+    let node = and(&"", json::node());
+    let parser = Parser::from(node.clone());
+    let program = Program::from(node);
+    measure("json", 20, || parser.try_parse(&text).is_ok());
+    measure("json program", 20, || program.try_parse(&text).is_ok());
+
+    // Every item parses the first alternative up to its end before it backtracks to the second one.
+    let node = all!(
+        &".items",
+        syntax(
+            ".items",
+            repeat(
+                ..,
+                any!(
+                    all!(store(repeat(1.., 'a')), set(".count", Set::Add(1)), 'b'),
+                    all!(store(repeat(1.., 'a')), 'c')
+                )
+            )
+        ),
+    );
+    let text = "aaaaaaaaac".repeat(1000);
+    let parser = Parser::from(node.clone());
+    let program = Program::from(node);
+    measure("alternatives", 20, || parser.try_parse(&text).is_ok());
+    measure("alternatives program", 20, || {
+        program.try_parse(&text).is_ok()
+    });
+}
@@ -1,7 +1,9 @@
+use crate::convert::*;
 use crate::generate::*;
 use crate::node::*;
 use crate::parse::*;
 use crate::*;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Unary {
@@ -28,31 +30,77 @@ pub enum Syntax {
     Binary(Binary, Box<Syntax>, Box<Syntax>),
 }
 
+pub fn converter() -> Converter<Syntax> {
+    fn unary(converter: Converter<Syntax>, kind: &str, unary: Unary) -> Converter<Syntax> {
+        converter.on(kind, move |_, children| {
+            let child = children.into_iter().next()?;
+            Some(Syntax::Unary(unary.clone(), child.into()))
+        })
+    }
+    fn binary(converter: Converter<Syntax>, kind: &str, binary: Binary) -> Converter<Syntax> {
+        converter.on(kind, move |tree, children| {
+            let named = |name: &str| tree.fields.iter().find(|field| field.0 == name);
+            let (left, right) = (named("left")?.1, named("right")?.1);
+            let mut children: Vec<_> = children.into_iter().map(Some).collect();
+            let left = children.get_mut(left)?.take()?;
+            let right = children.get_mut(right)?.take()?;
+            Some(Syntax::Binary(binary.clone(), left.into(), right.into()))
+        })
+    }
+
+    let converter = Converter::new().on("pre.number", |tree, _| {
+        Some(Syntax::Number(tree.values.first()?.parse().ok()?))
+    });
+    let converter = unary(converter, "pre.absolute", Unary::Absolute);
+    let converter = unary(converter, "pre.negate", Unary::Negate);
+    let converter = unary(converter, "pre.increment", Unary::PreIncrement);
+    let converter = unary(converter, "pre.decrement", Unary::PreDecrement);
+    let converter = unary(converter, "post.increment", Unary::PostIncrement);
+    let converter = unary(converter, "post.decrement", Unary::PostDecrement);
+    let converter = binary(converter, "post.add", Binary::Add);
+    let converter = binary(converter, "post.subtract", Binary::Subtract);
+    let converter = binary(converter, "post.multiply", Binary::Multiply);
+    binary(converter, "post.divide", Binary::Divide)
+}
+
+/// Converts 'tree' with a converter that is built once.
 pub fn convert(tree: &Tree) -> Option<Syntax> {
-    let unary = |unary| -> Option<Syntax> {
-        Some(Syntax::Unary(unary, convert(tree.children.get(0)?)?.into()))
-    };
-    let binary = |binary| -> Option<Syntax> {
-        Some(Syntax::Binary(
-            binary,
-            convert(tree.children.get(0)?)?.into(),
-            convert(tree.children.get(1)?)?.into(),
-        ))
-    };
-    Some(match tree.kind.as_str() {
-        "pre.number" => Syntax::Number(tree.values[0].parse().ok()?),
-        "pre.absolute" => unary(Unary::Absolute)?,
-        "pre.negate" => unary(Unary::Negate)?,
-        "pre.increment" => unary(Unary::PreIncrement)?,
-        "pre.decrement" => unary(Unary::PreDecrement)?,
-        "post.increment" => unary(Unary::PostIncrement)?,
-        "post.decrement" => unary(Unary::PostDecrement)?,
-        "post.add" => binary(Binary::Add)?,
-        "post.subtract" => binary(Binary::Subtract)?,
-        "post.multiply" => binary(Binary::Multiply)?,
-        "post.divide" => binary(Binary::Divide)?,
-        _ => panic!("Invalid kind '{}'.", tree.kind),
-    })
+    static CONVERTER: OnceLock<Converter<Syntax>> = OnceLock::new();
+    CONVERTER.get_or_init(converter).convert(tree).ok()
+}
+
+/// Builds the 'Syntax' of each syntax while parsing, such that no trees are converted.
+pub fn actions() -> Actions<Syntax> {
+    fn unary(actions: Actions<Syntax>, kind: &str, unary: Unary) -> Actions<Syntax> {
+        actions.on(kind, move |_, children| {
+            let child = children.into_iter().next()?;
+            Some(Syntax::Unary(unary.clone(), child.into()))
+        })
+    }
+    fn binary(actions: Actions<Syntax>, kind: &str, binary: Binary) -> Actions<Syntax> {
+        actions.on(kind, move |reduced, children| {
+            let named = |name: &str| reduced.fields.iter().find(|field| field.0 == name);
+            let (left, right) = (named("left")?.1, named("right")?.1);
+            let mut children: Vec<_> = children.into_iter().map(Some).collect();
+            let left = children.get_mut(left)?.take()?;
+            let right = children.get_mut(right)?.take()?;
+            Some(Syntax::Binary(binary.clone(), left.into(), right.into()))
+        })
+    }
+
+    let actions = Actions::new().on("pre.number", |reduced, _| {
+        Some(Syntax::Number(reduced.values.first()?.parse().ok()?))
+    });
+    let actions = unary(actions, "pre.absolute", Unary::Absolute);
+    let actions = unary(actions, "pre.negate", Unary::Negate);
+    let actions = unary(actions, "pre.increment", Unary::PreIncrement);
+    let actions = unary(actions, "pre.decrement", Unary::PreDecrement);
+    let actions = unary(actions, "post.increment", Unary::PostIncrement);
+    let actions = unary(actions, "post.decrement", Unary::PostDecrement);
+    let actions = binary(actions, "post.add", Binary::Add);
+    let actions = binary(actions, "post.subtract", Binary::Subtract);
+    let actions = binary(actions, "post.multiply", Binary::Multiply);
+    binary(actions, "post.divide", Binary::Divide)
 }
 
 pub fn node() -> Node {
@@ -62,41 +110,62 @@ pub fn node() -> Node {
         all!(space.clone(), node, space)
     }
     fn unary<N: ToNode>(operator: N) -> Node {
-        prefix(100, wrap(all!(operator, &"expression")))
+        wrap(all!(operator, &"unary"))
+    }
+    // An operator that is followed by an operand is not postfix, such that '1++2' adds '+2'.
+    fn suffix<N: ToNode>(operator: N) -> Node {
+        all!(&"postfix", wrap(operator), not(any!('0'..='9', '(')))
     }
-    fn binary<N: ToNode>(operator: N, precedence: usize, bind: Bind) -> Node {
-        postfix(precedence, bind, wrap(all!(wrap(operator), &"expression")))
+    fn binary<N: ToNode>(operator: N, left: &str, right: &str) -> Node {
+        all!(
+            field("left", refer(left)),
+            wrap(operator),
+            field("right", refer(right))
+        )
     }
     all!(
         define(".", &"expression"),
         define("~", repeat(.., any!(' ', '\n', '\r', '\t'))),
-        define("expression", precede(&"pre", &"post")),
+        define("expression", refer("sum")),
+        define("sum", any!(&"post.add", &"post.subtract", &"product")),
+        define("product", any!(&"post.multiply", &"post.divide", &"unary")),
         define(
-            "pre.group",
-            prefix(100, all!(wrap('('), &"expression", wrap(')')))
+            "unary",
+            any!(
+                &"pre.absolute",
+                &"pre.negate",
+                &"pre.increment",
+                &"pre.decrement",
+                &"postfix"
+            )
         ),
-        syntax(
-            "pre.number",
-            prefix(100, wrap(store(all!(repeat(1.., digit())))))
+        define(
+            "postfix",
+            any!(&"post.increment", &"post.decrement", &"primary")
         ),
+        define("primary", any!(&"pre.group", &"pre.number")),
+        define("pre.group", all!(wrap('('), &"expression", wrap(')'))),
+        syntax("pre.number", wrap(store(all!(repeat(1.., digit()))))),
         syntax("pre.absolute", unary('+')),
         syntax("pre.negate", unary('-')),
         syntax("pre.increment", unary("++")),
         syntax("pre.decrement", unary("--")),
-        syntax("post.increment", postfix(120, Bind::Left, "++")),
-        syntax("post.decrement", postfix(120, Bind::Left, "--")),
-        syntax("post.add", binary('+', 10, Bind::Left)),
-        syntax("post.subtract", binary('-', 10, Bind::Left)),
-        syntax("post.multiply", binary('*', 20, Bind::Left)),
-        syntax("post.divide", binary('/', 20, Bind::Left)),
+        syntax("post.increment", suffix("++")),
+        syntax("post.decrement", suffix("--")),
+        syntax("post.add", binary('+', "sum", "product")),
+        syntax("post.subtract", binary('-', "sum", "product")),
+        syntax("post.multiply", binary('*', "product", "unary")),
+        syntax("post.divide", binary('/', "product", "unary")),
     )
 }
 
 pub fn parse(text: &str) -> Option<Syntax> {
     Parser::from(and(&"", node()))
-        .parse(text)
-        .first()
-        .and_then(|tree| convert(&tree))
+        .memoize(4096)
+        .reduce(text, &actions())
+        .ok()?
+        .into_iter()
+        .next()
 }
 
 pub fn generate() -> Option<String> {
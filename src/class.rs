@@ -0,0 +1,180 @@
+use crate::node::*;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Formatter;
+use std::ops::RangeInclusive;
+
+/// Property tables generated by 'tools/ucd.pl'.
+mod table;
+
+/// Number of characters, which excludes the surrogate gap.
+pub const COUNT: u32 = dense(char::MAX) + 1;
+
+/// Position of 'symbol' among the characters where the surrogate gap is skipped.
+pub const fn dense(symbol: char) -> u32 {
+    let value = symbol as u32;
+    if value < 0xD800 {
+        value
+    } else {
+        value - 0x800
+    }
+}
+
+/// Character at the 'dense' position.
+pub fn sparse(dense: u32) -> char {
+    let value = if dense < 0xD800 { dense } else { dense + 0x800 };
+    char::from_u32(value).unwrap_or(char::MAX)
+}
+
+/// Number of characters in 'range'.
+pub fn size(range: &RangeInclusive<char>) -> u32 {
+    if range.is_empty() {
+        0
+    } else {
+        dense(*range.end()) - dense(*range.start()) + 1
+    }
+}
+
+/// Sorts the 'ranges' and merges the ones that overlap or touch such that they are disjoint.
+pub fn merge(ranges: impl IntoIterator<Item = RangeInclusive<char>>) -> Vec<RangeInclusive<char>> {
+    let mut ranges: Vec<_> = ranges
+        .into_iter()
+        .filter(|range| !range.is_empty())
+        .collect();
+    ranges.sort_by_key(|range| *range.start());
+    let mut merged: Vec<RangeInclusive<char>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if dense(*range.start()) <= dense(*last.end()) + 1 => {
+                if range.end() > last.end() {
+                    *last = *last.start()..=*range.end();
+                }
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Characters that are not in the sorted and disjoint 'ranges'.
+pub fn complement(ranges: &[RangeInclusive<char>]) -> Vec<RangeInclusive<char>> {
+    let mut complement = Vec::new();
+    let mut next = 0;
+    for range in ranges {
+        let start = dense(*range.start());
+        if start > next {
+            complement.push(sparse(next)..=sparse(start - 1));
+        }
+        next = dense(*range.end()) + 1;
+    }
+    if next < COUNT {
+        complement.push(sparse(next)..=char::MAX);
+    }
+    complement
+}
+
+/// Index of the range that contains 'symbol' with a binary search in the sorted and disjoint 'ranges'.
+pub fn search(ranges: &[RangeInclusive<char>], symbol: char) -> Option<usize> {
+    ranges
+        .binary_search_by(|range| {
+            if *range.end() < symbol {
+                Ordering::Less
+            } else if *range.start() > symbol {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .ok()
+}
+
+/// Splits the possibly overlapping 'ranges' into sorted and disjoint buckets where each bucket holds
+/// the indices of the ranges that contain it in their original order.
+pub fn buckets(ranges: &[RangeInclusive<char>]) -> Vec<(RangeInclusive<char>, Vec<usize>)> {
+    // The bounds are swept in order while the indices of the ranges that contain them are kept.
+    let mut bounds: Vec<_> = ranges
+        .iter()
+        .enumerate()
+        .filter(|(_, range)| !range.is_empty())
+        .flat_map(|(index, range)| {
+            [
+                (dense(*range.start()), index, true),
+                (dense(*range.end()) + 1, index, false),
+            ]
+        })
+        .collect();
+    bounds.sort_unstable();
+    let mut active = BTreeSet::new();
+    let mut buckets: Vec<(RangeInclusive<char>, Vec<usize>)> = Vec::new();
+    for (position, (bound, index, start)) in bounds.iter().copied().enumerate() {
+        if start {
+            active.insert(index);
+        } else {
+            active.remove(&index);
+        }
+        let next = match bounds.get(position + 1) {
+            Some(&(next, _, _)) if next > bound => next,
+            _ => continue,
+        };
+        if active.is_empty() {
+            continue;
+        }
+        let bucket = sparse(bound)..=sparse(next - 1);
+        let indices: Vec<_> = active.iter().copied().collect();
+        match buckets.last_mut() {
+            // Adjacent buckets with the same ranges are joined.
+            Some((last, last_indices))
+                if *last_indices == indices && dense(*last.end()) + 1 == bound =>
+            {
+                *last = *last.start()..=*bucket.end();
+            }
+            _ => buckets.push((bucket, indices)),
+        }
+    }
+    buckets
+}
+
+/// Sorted ranges of the characters that are digits in 'radix', which must be in '2..=36'.
+pub fn digits(radix: u32) -> Vec<RangeInclusive<char>> {
+    let last = |first: char, count: u32| char::from_u32(first as u32 + count - 1).unwrap_or(first);
+    let mut ranges = vec!['0'..=last('0', radix.min(10))];
+    if radix > 10 {
+        ranges.push('A'..=last('A', radix - 10));
+        ranges.push('a'..=last('a', radix - 10));
+    }
+    ranges
+}
+
+/// Writes 'range' as a character or as an inclusive range of characters.
+pub fn write(range: &RangeInclusive<char>, formatter: &mut Formatter) -> Result<(), fmt::Error> {
+    write!(formatter, "'{}'", range.start().escape_debug())?;
+    if range.start() != range.end() {
+        write!(formatter, "..='{}'", range.end().escape_debug())?;
+    }
+    Ok(())
+}
+
+/// Characters that may start an identifier.
+pub fn xid_start() -> Node {
+    Node::Class(table(table::XID_START), false)
+}
+
+/// Characters that may continue an identifier.
+pub fn xid_continue() -> Node {
+    Node::Class(table(table::XID_CONTINUE), false)
+}
+
+/// Characters with the 'Alphabetic' property.
+pub fn alphabetic() -> Node {
+    Node::Class(table(table::ALPHABETIC), false)
+}
+
+/// Characters with the 'White_Space' property.
+pub fn white_space() -> Node {
+    Node::Class(table(table::WHITE_SPACE), false)
+}
+
+fn table(table: &[(char, char)]) -> Vec<RangeInclusive<char>> {
+    table.iter().map(|&(low, high)| low..=high).collect()
+}
@@ -0,0 +1,150 @@
+use crate::node::*;
+use crate::parse::*;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// Visits a tree and its descendants in pre-order and post-order at once.
+pub trait TreeVisitor<'a> {
+    /// Called before the children of 'tree', which are skipped if it returns false.
+    fn enter(&mut self, _tree: &Tree<'a>) -> bool {
+        true
+    }
+
+    /// Called after the children of 'tree', or right after 'enter' if they were skipped.
+    fn exit(&mut self, _tree: &Tree<'a>) {}
+}
+
+/// Folds a tree bottom-up where each tree receives the outputs of its children in order.
+pub trait TreeFold<'a> {
+    type Output;
+
+    fn fold(&mut self, tree: &Tree<'a>, children: Vec<Self::Output>) -> Self::Output;
+}
+
+type Handle<T> = dyn Fn(&Tree, Vec<T>) -> Option<T> + Send + Sync;
+
+/// Converts trees into values with a handler per kind. A kind without a handler is converted by the handler
+/// of its closest family, such that a handler of '.b' converts '.b.0' unless '.b.0' has its own handler.
+pub struct Converter<T> {
+    handlers: HashMap<String, Box<Handle<T>>>,
+}
+
+/// A tree that could not be converted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// Kinds of the trees from the root to the tree that failed, with the index of each among its siblings.
+    pub path: Vec<(usize, String)>,
+    pub span: Range<usize>,
+    /// Set if no handler converts the kind of the tree, otherwise its handler failed.
+    pub unhandled: bool,
+}
+
+impl<'a> Tree<'a> {
+    pub fn visit(&self, visitor: &mut impl TreeVisitor<'a>) {
+        if visitor.enter(self) {
+            for child in self.children.iter() {
+                child.visit(visitor);
+            }
+        }
+        visitor.exit(self);
+    }
+
+    pub fn fold<F: TreeFold<'a>>(&self, fold: &mut F) -> F::Output {
+        let children = self.children.iter().map(|child| child.fold(fold)).collect();
+        fold.fold(self, children)
+    }
+}
+
+impl<T> Converter<T> {
+    pub fn new() -> Self {
+        Converter {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Converts the trees whose kind is a member of 'family' with 'handle', which receives the converted
+    /// children in order. If 'handle' returns 'None', the conversion fails at the tree.
+    pub fn on(
+        mut self,
+        family: &str,
+        handle: impl Fn(&Tree, Vec<T>) -> Option<T> + Send + Sync + 'static,
+    ) -> Self {
+        self.handlers.insert(family.into(), Box::new(handle));
+        self
+    }
+
+    fn handler(&self, kind: &str) -> Option<&Handle<T>> {
+        let mut family = kind;
+        loop {
+            if let Some(handle) = self.handlers.get(family) {
+                return Some(handle.as_ref());
+            }
+            family = &family[..family.rfind('.')?];
+        }
+    }
+
+    /// Sorted kinds of the syntaxes of 'node' that no handler converts.
+    pub fn unhandled(&self, node: &Node) -> Vec<String> {
+        let mut kinds = BTreeSet::new();
+        node.clone().descend(|node| {
+            if let Node::Spawn(_, kind) = &node {
+                if self.handler(kind).is_none() {
+                    kinds.insert(kind.clone());
+                }
+            }
+            node
+        });
+        kinds.into_iter().collect()
+    }
+
+    pub fn convert(&self, tree: &Tree) -> Result<T, Failure> {
+        fn next<T>(
+            converter: &Converter<T>,
+            tree: &Tree,
+            path: &mut Vec<(usize, String)>,
+        ) -> Result<T, Failure> {
+            let failure = |path: &Vec<(usize, String)>, unhandled| Failure {
+                path: path.clone(),
+                span: tree.span.clone(),
+                unhandled,
+            };
+            let handle = match converter.handler(&tree.kind) {
+                Some(handle) => handle,
+                None => return Err(failure(path, true)),
+            };
+            let mut children = Vec::with_capacity(tree.children.len());
+            for (index, child) in tree.children.iter().enumerate() {
+                path.push((index, child.kind.clone()));
+                children.push(next(converter, child, path)?);
+                path.pop();
+            }
+            handle(tree, children).ok_or_else(|| failure(path, false))
+        }
+
+        next(self, tree, &mut vec![(0, tree.kind.clone())])
+    }
+}
+
+impl<T> Default for Converter<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        let path = self
+            .path
+            .iter()
+            .map(|(index, kind)| format!("{}[{}]", kind, index))
+            .collect::<Vec<_>>()
+            .join(" > ");
+        if self.unhandled {
+            write!(formatter, "Unhandled kind at '{}'.", path)
+        } else {
+            write!(formatter, "Invalid tree at '{}'.", path)
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::parse::*;
+use std::cmp::Reverse;
+use std::ops::Range;
+
+/// A lossless concrete syntax tree where every byte of the source text belongs to exactly one leaf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cst<'a> {
+    /// A syntax with its values, children and unstored text in text order.
+    Node {
+        kind: String,
+        span: Range<usize>,
+        children: Vec<Cst<'a>>,
+    },
+    /// Text of a store.
+    Value(&'a str, Range<usize>),
+    /// Unstored text that is not white space.
+    Symbols(&'a str, Range<usize>),
+    /// Unstored white space. It is told apart from the other unstored text by its characters rather than by
+    /// the rule that parsed it.
+    Whitespace(&'a str, Range<usize>),
+}
+
+impl<'a> Cst<'a> {
+    /// Builds the lossless trees of 'text' from the 'trees' that were parsed from it. The text that no tree
+    /// covers is kept at the top level. A store that overlaps a child tree is left to the child and the text
+    /// of stores that overlap each other is kept once.
+    pub fn build(text: &'a str, trees: &[Tree<'a>]) -> Vec<Cst<'a>> {
+        fn gap<'a>(text: &'a str, span: Range<usize>, csts: &mut Vec<Cst<'a>>) {
+            let mut start = span.start;
+            while start < span.end {
+                let slice = &text[start..span.end];
+                let whitespace = slice.starts_with(char::is_whitespace);
+                let length = slice
+                    .find(|symbol: char| symbol.is_whitespace() != whitespace)
+                    .unwrap_or(slice.len());
+                let range = start..start + length;
+                if whitespace {
+                    csts.push(Cst::Whitespace(&text[range.clone()], range));
+                } else {
+                    csts.push(Cst::Symbols(&text[range.clone()], range));
+                }
+                start += length;
+            }
+        }
+
+        /// Appends the 'items' in text order with the text between them, from 'span.start' to 'span.end'.
+        fn fill<'a>(
+            text: &'a str,
+            span: Range<usize>,
+            mut items: Vec<Cst<'a>>,
+            csts: &mut Vec<Cst<'a>>,
+        ) {
+            items.sort_by_key(|item| item.span().start);
+            let mut index = span.start;
+            for item in items {
+                let item_span = item.span();
+                if item_span.start > index {
+                    gap(text, index..item_span.start, csts);
+                }
+                index = index.max(item_span.end);
+                csts.push(item);
+            }
+            if span.end > index {
+                gap(text, index..span.end, csts);
+            }
+        }
+
+        fn next<'a>(text: &'a str, tree: &Tree<'a>) -> Cst<'a> {
+            let children: Vec<_> = tree
+                .children
+                .iter()
+                .map(|child| next(text, child))
+                .collect();
+            let spans: Vec<_> = children.iter().map(Cst::span).collect();
+            let mut stores: Vec<_> = tree
+                .spans
+                .iter()
+                .filter(|value| {
+                    spans
+                        .iter()
+                        .all(|span| value.end <= span.start || span.end <= value.start)
+                })
+                .collect();
+            // Stores may nest or overlap, such that a value covered by an earlier value is dropped and a
+            // value that is partially covered keeps the text that follows it.
+            stores.sort_by_key(|span| (span.start, Reverse(span.end)));
+            let mut values = Vec::new();
+            let mut covered = 0;
+            for span in stores {
+                if span.end > covered || (span.is_empty() && span.start >= covered) {
+                    let start = span.start.max(covered);
+                    values.push(Cst::Value(&text[start..span.end], start..span.end));
+                    covered = span.end;
+                }
+            }
+            let items: Vec<_> = values.into_iter().chain(children).collect();
+            let start = items
+                .iter()
+                .map(|item| item.span().start)
+                .fold(tree.span.start, usize::min);
+            let end = items
+                .iter()
+                .map(|item| item.span().end)
+                .fold(tree.span.end, usize::max);
+            let mut csts = Vec::new();
+            fill(text, start..end, items, &mut csts);
+            Cst::Node {
+                kind: tree.kind.clone(),
+                span: start..end,
+                children: csts,
+            }
+        }
+
+        let roots = trees.iter().map(|tree| next(text, tree)).collect();
+        let mut csts = Vec::new();
+        fill(text, 0..text.len(), roots, &mut csts);
+        csts
+    }
+
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Cst::Node { span, .. }
+            | Cst::Value(_, span)
+            | Cst::Symbols(_, span)
+            | Cst::Whitespace(_, span) => span.clone(),
+        }
+    }
+
+    /// Leaves of the tree in text order.
+    pub fn leaves(&self) -> Vec<&Cst<'a>> {
+        fn next<'a, 'b>(cst: &'b Cst<'a>, leaves: &mut Vec<&'b Cst<'a>>) {
+            match cst {
+                Cst::Node { children, .. } => {
+                    for child in children {
+                        next(child, leaves);
+                    }
+                }
+                leaf => leaves.push(leaf),
+            }
+        }
+
+        let mut leaves = Vec::new();
+        next(self, &mut leaves);
+        leaves
+    }
+
+    /// Concatenates the texts of the leaves, which is the source text that the tree covers.
+    pub fn text(&self) -> String {
+        self.leaves()
+            .into_iter()
+            .map(|leaf| match leaf {
+                Cst::Value(text, _) | Cst::Symbols(text, _) | Cst::Whitespace(text, _) => *text,
+                Cst::Node { .. } => "",
+            })
+            .collect()
+    }
+}
+
+impl Parser {
+    /// Parses 'text' into lossless trees whose leaves concatenate into 'text'.
+    pub fn parse_lossless<'a>(&self, text: &'a str) -> Result<Vec<Cst<'a>>, Error> {
+        let trees = self.try_parse(text)?;
+        Ok(Cst::build(text, &trees))
+    }
+}
@@ -1,9 +1,13 @@
+use crate::class;
 use crate::node::*;
+use crate::parse::*;
 use rand;
 use rand::rngs::ThreadRng;
 use rand::seq::SliceRandom;
+use rand::RngExt;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::ops::Range;
+use std::sync::Arc;
 use Identifier::*;
 use Node::*;
 
@@ -13,6 +17,15 @@ pub struct Generator {
     references: Vec<Generate>,
     node_indices: HashMap<Identifier, usize>,
     value_indices: HashMap<Identifier, usize>,
+    /// Index of the '.depth' value if the grammar has syntaxes.
+    depth: Option<usize>,
+    /// Validates the outputs of grammars with lookaheads or predicates since lookaheads are not generated and
+    /// predicates only see a sampled candidate instead of the text that follows them. It is memoized since
+    /// the outputs of recursive grammars nest deeply.
+    parser: Option<Parser>,
+    /// Whether the grammar has conditions, lookaheads or predicates that an output may fail, in which case
+    /// the generation is bounded by 'ATTEMPTS' and 'DEPTH'.
+    bounded: bool,
 }
 
 struct State<'a> {
@@ -20,38 +33,100 @@ struct State<'a> {
     pub random: ThreadRng,
     pub references: &'a Vec<Generate>,
     pub precedence: usize,
+    pub values: Vec<isize>,
+    pub saves: Vec<(usize, isize)>,
+    /// Number of references being generated.
+    pub nesting: usize,
+    /// Maximum number of nested references.
+    pub limit: usize,
+    pub depth: Option<usize>,
+    /// Start indices of the stores and syntaxes being generated.
+    pub indices: Vec<usize>,
+    /// Span of the text of the last store.
+    pub store: Range<usize>,
+    pub captures: Vec<((String, String), isize)>,
 }
 
-type Generate = Rc<dyn Fn(&mut State) -> bool>;
+type Generate = Arc<dyn Fn(&mut State) -> bool + Send + Sync>;
+
+impl State<'_> {
+    /// Depth of the syntax being generated.
+    fn level(&self) -> isize {
+        self.depth.map_or(0, |depth| self.values[depth])
+    }
+}
 
 impl Generator {
+    /// Maximum number of outputs generated until one of them satisfies the conditions and the lookaheads
+    /// of a bounded grammar.
+    const ATTEMPTS: usize = 256;
+    /// Number of nested references beyond which an 'Or' tries its alternatives without references first,
+    /// which bounds the output of grammars that are likely to recurse. A deeper reference of a bounded grammar
+    /// fails such that an enclosing 'Or' falls back to its other alternatives.
+    const DEPTH: usize = 32;
+    /// Maximum number of candidate texts given to a predicate until it consumes one of them.
+    const CANDIDATES: usize = 64;
+    /// Exclusive upper bound of the generated integers.
+    const INTEGER: u32 = 16;
+
     pub fn generate(&self) -> Option<String> {
-        let mut state = State {
-            text: String::new(),
-            random: rand::thread_rng(),
-            references: &self.references,
-            precedence: 0,
+        let (attempts, limit) = if self.bounded {
+            (Self::ATTEMPTS, Self::DEPTH)
+        } else {
+            (1, usize::MAX)
         };
+        for _ in 0..attempts {
+            let mut state = State {
+                text: String::new(),
+                random: rand::rng(),
+                references: &self.references,
+                precedence: 0,
+                values: vec![0; self.value_indices.len()],
+                saves: Vec::new(),
+                nesting: 0,
+                limit,
+                depth: self.depth,
+                indices: Vec::new(),
+                store: 0..0,
+                captures: Vec::new(),
+            };
 
-        if (self.root)(&mut state) {
-            Some(state.text)
-        } else {
-            None
+            if (self.root)(&mut state) {
+                match &self.parser {
+                    Some(parser) if parser.try_parse(&state.text).is_err() => continue,
+                    _ => return Some(state.text),
+                }
+            }
         }
+        None
     }
 }
 
+/// Writes 'value' in 'radix' with lowercase digits.
+fn integer(value: u32, radix: u32) -> String {
+    let mut digits = Vec::new();
+    let mut value = value;
+    loop {
+        digits.push(char::from_digit(value % radix, radix).unwrap_or('0'));
+        value /= radix;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.iter().rev().collect()
+}
+
 impl From<Node> for Generator {
     fn from(node: Node) -> Generator {
         fn next(node: &Node, generators: &Vec<Option<Generate>>) -> Generate {
             match node {
-                True => Rc::new(|_| true),
-                False => Rc::new(|_| false),
+                True => Arc::new(|_| true),
+                False => Arc::new(|_| false),
                 And(_, _) => {
                     let nodes = node.flatten();
                     let generators: Vec<_> =
                         nodes.iter().map(|node| next(node, generators)).collect();
-                    Rc::new(move |state| {
+                    Arc::new(move |state| {
                         for generator in &generators {
                             if generator(state) {
                                 continue;
@@ -65,38 +140,173 @@ impl From<Node> for Generator {
                     let nodes = node.flatten();
                     let generators: Vec<_> =
                         nodes.iter().map(|node| next(node, generators)).collect();
-                    Rc::new(move |state| {
-                        for generator in
-                            generators.choose_multiple(&mut state.random, generators.len())
-                        {
-                            if generator(state) {
+                    let recursive: Vec<_> = nodes.iter().map(|node| refers(node)).collect();
+                    Arc::new(move |state| {
+                        let length = state.text.len();
+                        let values = state.values.clone();
+                        let saves = state.saves.clone();
+                        let indices = state.indices.clone();
+                        let captures = state.captures.clone();
+                        let mut order: Vec<_> = (0..generators.len()).collect();
+                        order.shuffle(&mut state.random);
+                        if state.nesting >= Generator::DEPTH {
+                            order.sort_by_key(|&index| recursive[index]);
+                        }
+                        for index in order {
+                            if generators[index](state) {
                                 return true;
                             }
+                            state.text.truncate(length);
+                            state.values.clone_from(&values);
+                            state.saves.clone_from(&saves);
+                            state.indices.clone_from(&indices);
+                            state.captures.clone_from(&captures);
                         }
                         false
                     })
                 }
                 Refer(Index(index)) => {
                     let index = *index;
-                    match &generators[index] {
-                        Some(generator) => generator.clone(),
-                        None => Rc::new(move |state| state.references[index].clone()(state)),
-                    }
+                    let generator = generators[index].clone();
+                    Arc::new(move |state| {
+                        if state.nesting >= state.limit {
+                            return false;
+                        }
+                        state.nesting += 1;
+                        let success = match &generator {
+                            Some(generator) => generator(state),
+                            None => state.references[index].clone()(state),
+                        };
+                        state.nesting -= 1;
+                        success
+                    })
                 }
-                Spawn(_) => next(&True, generators),
+                // A syntax consumes its start index and its captures go out of scope.
+                Spawn(_, _) => Arc::new(|state| {
+                    state.indices.pop();
+                    let depth = state.level();
+                    state.captures.retain(|capture| capture.1 <= depth);
+                    true
+                }),
                 Depth(_) => next(&True, generators),
-                Store(_, _) => next(&True, generators),
+                &Store(shift, Stack::Push) => Arc::new(move |state| {
+                    state.indices.push(state.text.len() - shift);
+                    true
+                }),
+                &Store(shift, Stack::Pop) => Arc::new(move |state| match state.indices.pop() {
+                    Some(index) => {
+                        state.store = index..state.text.len() - shift;
+                        true
+                    }
+                    None => false,
+                }),
+                Capture(name) => {
+                    let name = name.clone();
+                    Arc::new(move |state| {
+                        let text = state.text[state.store.clone()].to_string();
+                        let depth = state.level();
+                        state.captures.push(((name.clone(), text), depth));
+                        true
+                    })
+                }
+                Back(name) => {
+                    let name = name.clone();
+                    Arc::new(move |state| {
+                        let depth = state.level();
+                        let found = state
+                            .captures
+                            .iter()
+                            .rev()
+                            .find(|capture| capture.0 .0 == name && capture.1 <= depth);
+                        match found {
+                            Some(((_, text), _)) => {
+                                let text = text.clone();
+                                state.text.push_str(&text);
+                                true
+                            }
+                            None => false,
+                        }
+                    })
+                }
                 Precede(_, _, _) => next(&True, generators),
+                Recover(node, _) => next(node, generators),
+                Cut => next(&True, generators),
+                // Names do not change the generated text.
+                Label(_) | Field(_, _) => next(&True, generators),
+                // Lookaheads and predicates are verified by parsing the output, such that an output
+                // that fails a predicate is generated again. A predicate consumes no generated text.
+                Peek(_) | Not(_) => next(&True, generators),
+                // The predicate is given random printable candidates and the prefix that it consumes is kept.
+                Native(slots, predicate) => {
+                    let indices: Vec<_> = slots
+                        .iter()
+                        .map(|slot| match slot {
+                            &Index(index) => index,
+                            slot => panic!("Invalid slot '{:?}'.", slot),
+                        })
+                        .collect();
+                    let predicate = predicate.clone();
+                    Arc::new(move |state| {
+                        let values: Vec<_> =
+                            indices.iter().map(|&index| state.values[index]).collect();
+                        for _ in 0..Generator::CANDIDATES {
+                            let length = state.random.random_range(1..=8);
+                            let candidate: String = (0..length)
+                                .map(|_| state.random.random_range(' '..='~'))
+                                .collect();
+                            match (predicate.0)(&candidate, &values) {
+                                Some(length) if candidate.is_char_boundary(length) => {
+                                    state.text.push_str(&candidate[..length]);
+                                    return true;
+                                }
+                                _ => {}
+                            }
+                        }
+                        false
+                    })
+                }
+                Set(Index(index), set) => {
+                    let (index, set) = (*index, set.clone());
+                    Arc::new(move |state| {
+                        state.values[index] = set.apply(state.values[index], &state.values);
+                        true
+                    })
+                }
+                &If(Index(left), compare, Index(right)) => {
+                    Arc::new(move |state| compare.compare(state.values[left], state.values[right]))
+                }
+                &Push(Index(index)) => Arc::new(move |state| {
+                    state.saves.push((index, state.values[index]));
+                    true
+                }),
+                &Pop(Index(index)) => Arc::new(move |state| match state.saves.pop() {
+                    Some((slot, value)) if slot == index => {
+                        state.values[index] = value;
+                        true
+                    }
+                    _ => false,
+                }),
+                // Integers are kept small such that they do not drive long repetitions.
+                &Integer(Index(index), radix) => Arc::new(move |state| {
+                    let value = state.random.random_range(0..Generator::INTEGER);
+                    state.text.push_str(&integer(value, radix));
+                    state.values[index] = value as isize;
+                    true
+                }),
+                Times(Index(index), node) => {
+                    let (index, generator) = (*index, next(node, generators));
+                    Arc::new(move |state| (0..state.values[index]).all(|_| generator(state)))
+                }
                 Symbol(symbol) => {
                     let symbol = *symbol;
-                    Rc::new(move |state| {
+                    Arc::new(move |state| {
                         state.text.push(symbol);
                         true
                     })
                 }
                 Text(text) => {
                     let text = text.clone();
-                    Rc::new(move |state| {
+                    Arc::new(move |state| {
                         state.text.push_str(text.as_str());
                         true
                     })
@@ -104,15 +314,85 @@ impl From<Node> for Generator {
                 Switch(cases) => {
                     let mut nodes = Vec::new();
                     for case in cases {
-                        nodes.push(and(case.0, case.1.clone()));
+                        nodes.push(and(case.0.clone(), case.1.clone()));
                     }
                     next(&any(nodes), generators)
                 }
+                Class(ranges, negate) => {
+                    let ranges = if *negate {
+                        class::complement(ranges)
+                    } else {
+                        ranges.clone()
+                    };
+                    let count: u32 = ranges.iter().map(class::size).sum();
+                    Arc::new(move |state| {
+                        if count == 0 {
+                            return false;
+                        }
+                        // Sample uniformly among the characters of all the ranges.
+                        let mut index = state.random.random_range(0..count);
+                        for range in ranges.iter() {
+                            let size = class::size(range);
+                            if index < size {
+                                let dense = class::dense(*range.start()) + index;
+                                state.text.push(class::sparse(dense));
+                                return true;
+                            }
+                            index -= size;
+                        }
+                        false
+                    })
+                }
                 node => panic!("Invalid node '{}'.", node),
             }
         }
 
+        fn refers(node: &Node) -> bool {
+            match node {
+                Refer(_) => true,
+                And(left, right) | Or(left, right) | Recover(left, right) => {
+                    refers(left) || refers(right)
+                }
+                Shift(_, node) | Times(_, node) | Peek(node) | Not(node) => refers(node),
+                Switch(cases) => cases.iter().any(|case| refers(&case.1)),
+                _ => false,
+            }
+        }
+
+        fn condition(node: &Node) -> bool {
+            match node {
+                If(_, _, _) => true,
+                And(left, right) | Or(left, right) | Recover(left, right) => {
+                    condition(left) || condition(right)
+                }
+                Define(_, node) | Shift(_, node) | Times(_, node) | Peek(node) | Not(node) => {
+                    condition(node)
+                }
+                Switch(cases) => cases.iter().any(|case| condition(&case.1)),
+                _ => false,
+            }
+        }
+
+        fn lookahead(node: &Node) -> bool {
+            match node {
+                Peek(_) | Not(_) | Native(_, _) => true,
+                And(left, right) | Or(left, right) | Recover(left, right) => {
+                    lookahead(left) || lookahead(right)
+                }
+                Define(_, node) | Shift(_, node) | Times(_, node) => lookahead(node),
+                Switch(cases) => cases.iter().any(|case| lookahead(&case.1)),
+                _ => false,
+            }
+        }
+
+        let parser = if lookahead(&node) {
+            Some(Parser::from(node.clone()).memoize(4096))
+        } else {
+            None
+        };
+        let bounded = parser.is_some() || condition(&node);
         let (node, nodes, node_indices, value_indices) = node.resolve();
+        let depth = value_indices.get(&Path(".depth".into())).copied();
         let mut generators = vec![None; nodes.len()];
         for i in 0..nodes.len() {
             generators[i] = Some(next(&nodes[i], &generators));
@@ -127,6 +407,9 @@ impl From<Node> for Generator {
             references,
             node_indices,
             value_indices,
+            depth,
+            parser,
+            bounded,
         }
     }
 }
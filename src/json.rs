@@ -1,7 +1,10 @@
+use crate::convert::*;
 use crate::generate::*;
 use crate::node::*;
 use crate::parse::*;
+use crate::unparse::*;
 use crate::*;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Syntax {
@@ -13,41 +16,92 @@ pub enum Syntax {
     Object(Vec<(Syntax, Syntax)>),
 }
 
-pub fn convert(tree: &Tree) -> Option<Syntax> {
-    Some(match tree.kind.as_str() {
-        ".null" => Syntax::Null,
-        ".number" => Syntax::Number(tree.values[0].parse().ok()?),
-        ".true" => Syntax::Boolean(true),
-        ".false" => Syntax::Boolean(false),
-        ".string" => Syntax::String(tree.values[0].into()),
-        ".array" => {
-            let mut items = Vec::new();
-            for child in tree.children.iter() {
-                items.push(convert(child)?);
+pub fn converter() -> Converter<Syntax> {
+    Converter::new()
+        .on(".null", |_, _| Some(Syntax::Null))
+        .on(".number", |tree, _| {
+            Some(Syntax::Number(tree.values.first()?.parse().ok()?))
+        })
+        .on(".true", |_, _| Some(Syntax::Boolean(true)))
+        .on(".false", |_, _| Some(Syntax::Boolean(false)))
+        .on(".string", |tree, _| {
+            Some(Syntax::String((*tree.values.first()?).into()))
+        })
+        .on(".array", |_, children| Some(Syntax::Array(children)))
+        .on(".object", |tree, children| {
+            let mut children: Vec<_> = children.into_iter().map(Some).collect();
+            let mut take = |name: &str| -> Option<Vec<_>> {
+                tree.fields
+                    .iter()
+                    .filter(|field| field.0 == name)
+                    .map(|field| children.get_mut(field.1)?.take())
+                    .collect()
+            };
+            let (keys, values) = (take("key")?, take("value")?);
+            if keys.len() != values.len() {
+                return None;
             }
-            Syntax::Array(items)
-        }
-        ".object" => {
-            let mut pairs = Vec::new();
-            let mut children = tree.children.iter();
-            while let (Some(key), Some(value)) = (children.next(), children.next()) {
-                pairs.push((convert(key)?, convert(value)?));
+            Some(Syntax::Object(keys.into_iter().zip(values).collect()))
+        })
+}
+
+/// Converts 'tree' with a converter that is built once.
+pub fn convert(tree: &Tree) -> Option<Syntax> {
+    static CONVERTER: OnceLock<Converter<Syntax>> = OnceLock::new();
+    CONVERTER.get_or_init(converter).convert(tree).ok()
+}
+
+/// Builds the 'Syntax' of each syntax while parsing, such that no trees are converted.
+pub fn actions() -> Actions<Syntax> {
+    Actions::new()
+        .on(".null", |_, _| Some(Syntax::Null))
+        .on(".number", |reduced, _| {
+            Some(Syntax::Number(reduced.values.first()?.parse().ok()?))
+        })
+        .on(".true", |_, _| Some(Syntax::Boolean(true)))
+        .on(".false", |_, _| Some(Syntax::Boolean(false)))
+        .on(".string", |reduced, _| {
+            Some(Syntax::String((*reduced.values.first()?).into()))
+        })
+        .on(".array", |_, children| Some(Syntax::Array(children)))
+        .on(".object", |reduced, children| {
+            let mut children: Vec<_> = children.into_iter().map(Some).collect();
+            let mut take = |name: &str| -> Option<Vec<_>> {
+                reduced
+                    .fields
+                    .iter()
+                    .filter(|field| field.0 == name)
+                    .map(|field| children.get_mut(field.1)?.take())
+                    .collect()
+            };
+            let (keys, values) = (take("key")?, take("value")?);
+            if keys.len() != values.len() {
+                return None;
             }
-            Syntax::Object(pairs)
-        }
-        _ => panic!("Invalid kind '{}'.", tree.kind),
-    })
+            Some(Syntax::Object(keys.into_iter().zip(values).collect()))
+        })
 }
 
 pub fn node() -> Node {
     fn wrap<N: ToNode>(node: N) -> Node {
         all!(&"~", node, &"~")
     }
-    let pair = || all!(&".string", wrap(':'), &"");
+    let pair = || {
+        all!(
+            field("key", refer(".string")),
+            wrap(':'),
+            field("value", refer(""))
+        )
+    };
     let digit = || all!('0'..='9');
     let hex = || all!('u', repeat(4..4, any!(digit(), 'a'..='f', 'A'..='F')));
     let escape = || all!('\\', any!('\\', '/', '"', 'b', 'f', 'n', 'r', 't', hex()));
-    let letter = || any!(escape(), 'a'..='z', 'A'..'Z');
+    let letter = || {
+        any!(
+            escape(),
+            none_of(['"'..='"', '\\'..='\\', '\u{0}'..='\u{1f}'])
+        )
+    };
     let integer = || all!(option('-'), any!('0', all!('1'..='9', repeat(.., digit()))));
     let fraction = || all!('.', repeat(1.., digit()));
     let exponent = || all!(any!('e', 'E'), option(any!('+', '-')), repeat(1.., digit()));
@@ -72,7 +126,49 @@ pub fn parser() -> Parser {
 }
 
 pub fn parse(text: &str) -> Option<Syntax> {
-    parser().parse(text).first().and_then(|tree| convert(&tree))
+    parser().reduce(text, &actions()).ok()?.into_iter().next()
+}
+
+/// Builds the tree that 'syntax' parses from, with empty spans.
+pub fn tree(syntax: &Syntax) -> OwnedTree {
+    let (kind, values, children) = match syntax {
+        Syntax::Null => (".null", vec![], vec![]),
+        Syntax::Number(number) => (".number", vec![number.to_string()], vec![]),
+        Syntax::Boolean(true) => (".true", vec![], vec![]),
+        Syntax::Boolean(false) => (".false", vec![], vec![]),
+        Syntax::String(string) => (".string", vec![string.clone()], vec![]),
+        Syntax::Array(items) => (".array", vec![], items.iter().map(tree).collect()),
+        Syntax::Object(pairs) => (
+            ".object",
+            vec![],
+            pairs
+                .iter()
+                .flat_map(|(key, value)| vec![tree(key), tree(value)])
+                .collect(),
+        ),
+    };
+    let fields = match syntax {
+        Syntax::Object(_) => (0..children.len())
+            .map(|index| (if index % 2 == 0 { "key" } else { "value" }.into(), index))
+            .collect(),
+        _ => Vec::new(),
+    };
+    OwnedTree {
+        kind: kind.into(),
+        values,
+        children,
+        fields,
+        ..OwnedTree::default()
+    }
+}
+
+pub fn unparser() -> Unparser {
+    Unparser::from(and(refer(""), node()))
+}
+
+/// Prints 'syntax' as compact text, or 'None' if it has no text such as a number that is not finite.
+pub fn print(syntax: &Syntax) -> Option<String> {
+    unparser().print(&tree(syntax).as_tree())
 }
 
 pub fn generator() -> Generator {
@@ -1,7 +1,14 @@
 pub mod arith;
+pub mod class;
+pub mod convert;
+pub mod cst;
 pub mod family;
 pub mod generate;
 pub mod graph;
 pub mod json;
+pub mod machine;
 pub mod node;
 pub mod parse;
+pub mod query;
+pub mod script;
+pub mod unparse;
@@ -0,0 +1,1031 @@
+use crate::class;
+use crate::node::*;
+use crate::node::{If, Set};
+use crate::parse::*;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::mem;
+use std::ops::RangeInclusive;
+use Identifier::*;
+use Node::*;
+
+/// An instruction of a compiled 'Program' where addresses are indices in the instructions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    /// Ends the parse successfully.
+    End,
+    Fail,
+    Jump(usize),
+    /// Pushes a backtrack point that resumes at the address.
+    Choice(usize),
+    /// Pops the backtrack point of the current alternative and jumps to the address.
+    Commit(usize),
+    /// Prevents the backtrack points so far from resuming.
+    Cut,
+    /// Calls the reference at the index.
+    Call(usize),
+    Return,
+    /// Records the rule as expected if the following call fails.
+    Rule(String),
+    /// Pops the frame of a 'Rule'.
+    Pop,
+    /// Starts growing a seed of the left recursive reference at the index. It is followed by the 'Call'
+    /// of the reference and a 'Grown' that parses the reference again as long as it consumes more text.
+    Grow(usize),
+    Grown,
+    /// Starts a recovery where the 'sync' address is the predicate that ends the skipped text and the
+    /// 'end' address is where the parse resumes after a recovery.
+    Recover {
+        sync: usize,
+        end: usize,
+    },
+    /// Pops the frame of a 'Recover' whose node succeeded and jumps to its end.
+    Recovered,
+    /// Pops the frame of a successful 'sync' predicate and jumps to the end of its 'Recover'.
+    Synced,
+    /// Starts a positive lookahead that resumes at the address if its node succeeds.
+    Peek(usize),
+    /// Starts a negative lookahead that resumes at the address if its node fails.
+    Not(usize),
+    /// Pops the frame of a 'Peek' or a 'Not' whose node succeeded and backtracks to where it started.
+    Looked,
+    Symbol(char),
+    Text(String),
+    /// Consumes a symbol and jumps to the address of the sorted and disjoint range that contains it.
+    Switch(Vec<RangeInclusive<char>>, Vec<usize>),
+    Class(Vec<RangeInclusive<char>>, bool),
+    Spawn(usize, String),
+    Store(usize, Stack),
+    Capture(String),
+    Back(String),
+    /// Names the last store.
+    Label(String),
+    /// Marks the number of trees, or pops the mark and names the last tree if it was pushed since then.
+    Field(String, Stack),
+    Precede(usize, Bind, Stack),
+    Set(usize, Set),
+    If(usize, If, usize),
+    /// Saves the value of the slot at the index.
+    Save(usize),
+    /// Restores the last saved value of the slot at the index.
+    Restore(usize),
+    /// Parses an integer in the radix into the slot at the index.
+    Integer(usize, u32),
+    /// Starts a repetition as many times as the value of the slot at the index, or jumps to the address if
+    /// it is not positive.
+    Times(usize, usize),
+    /// Ends an iteration of a 'Times' and jumps to the address if iterations remain.
+    Again(usize),
+    /// Calls the predicate with the values of the slots at the indices.
+    Native(Vec<usize>, Predicate),
+}
+
+/// A flat array of instructions compiled from a resolved 'Node' and run by a loop-based virtual machine.
+/// Unlike a 'Parser', a 'Program' does not memoize references, enumerate ambiguous parses or reduce
+/// syntaxes with actions, such that it has no 'memoize', 'parse_all' or 'reduce'.
+#[derive(Clone)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    /// Addresses of the references.
+    entries: Vec<usize>,
+    root: usize,
+    indices: HashMap<Identifier, usize>,
+    depth: usize,
+}
+
+/// A point that the machine returns to when a failure unwinds the frames.
+enum Frame<'a> {
+    Choice {
+        address: usize,
+        checkpoint: Checkpoint,
+        calls: usize,
+        cuts: usize,
+    },
+    Rule {
+        rule: String,
+        index: usize,
+    },
+    /// The number of iterations that remain in a repetition.
+    Times(isize),
+    /// Boxed since it is much larger than the other frames.
+    Grow(Box<Grow<'a>>),
+    Recover {
+        sync: usize,
+        end: usize,
+        checkpoint: Checkpoint,
+        failure: (usize, Vec<Expect>),
+        calls: usize,
+        cuts: usize,
+    },
+    Look {
+        negate: bool,
+        end: usize,
+        checkpoint: Checkpoint,
+        /// The failure before a negative lookahead whose expectations are discarded.
+        failure: Option<(usize, Vec<Expect>)>,
+        calls: usize,
+        cuts: usize,
+    },
+    /// A skipped text that ends when 'sync' succeeds at the current index.
+    Sync {
+        sync: usize,
+        end: usize,
+        checkpoint: Checkpoint,
+        start: usize,
+        error: (usize, Vec<Expect>),
+        failure: (usize, Vec<Expect>),
+        calls: usize,
+        cuts: usize,
+    },
+}
+
+/// A seed of a left recursive reference that is being grown.
+struct Grow<'a> {
+    key: (usize, usize),
+    /// Address of the 'Call' that follows the 'Grow'.
+    address: usize,
+    origin: Checkpoint,
+    start: Lengths,
+    lows: Lengths,
+    calls: usize,
+    cuts: usize,
+    /// The best effect so far and the text index that it reached.
+    best: Option<(Effect<'a>, usize)>,
+}
+
+impl Frame<'_> {
+    /// Takes the checkpoint that the frame would restore, if any.
+    fn checkpoint(self) -> Option<Checkpoint> {
+        match self {
+            Frame::Rule { .. } | Frame::Times(_) => None,
+            Frame::Grow(grow) => Some(grow.origin),
+            Frame::Choice { checkpoint, .. }
+            | Frame::Recover { checkpoint, .. }
+            | Frame::Look { checkpoint, .. }
+            | Frame::Sync { checkpoint, .. } => Some(checkpoint),
+        }
+    }
+}
+
+impl Program {
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn parse<'a>(&self, text: &'a str) -> Vec<Tree<'a>> {
+        self.try_parse(text).unwrap_or_default()
+    }
+
+    pub fn try_parse<'a>(&self, text: &'a str) -> Result<Vec<Tree<'a>>, Error> {
+        let (trees, mut diagnostics) = self.recover(text);
+        if diagnostics.is_empty() {
+            Ok(trees)
+        } else {
+            Err(diagnostics.remove(0))
+        }
+    }
+
+    /// Parses 'text' while recovering from errors with 'recover' nodes as 'Parser::recover' does.
+    pub fn recover<'a>(&self, text: &'a str) -> (Vec<Tree<'a>>, Vec<Error>) {
+        let references = Vec::new();
+        let failure = RefCell::new((0, Vec::new()));
+        let memo = RefCell::new(Memo {
+            capacity: 0,
+            entries: BTreeMap::new(),
+            count: 0,
+            seeds: HashMap::new(),
+        });
+        let mut state = State {
+            index: 0,
+            text,
+            references: &references,
+            trees: Vec::new(),
+            precedences: Vec::new(),
+            marks: Vec::new(),
+            indices: Vec::new(),
+            stores: Vec::new(),
+            precedence: 0,
+            values: vec![0; self.indices.len()],
+            saves: Vec::new(),
+            captures: Vec::new(),
+            labels: Vec::new(),
+            results: Vec::new(),
+            actions: None,
+            depth: self.depth,
+            failure: &failure,
+            memo: &memo,
+            lows: Lengths::default(),
+            diagnostics: Vec::new(),
+            choices: None,
+            trail: Vec::new(),
+            checkpoints: 0,
+            cuts: 0,
+        };
+        let success = self.run(&mut state);
+        state.conclude(success)
+    }
+
+    fn run<'a>(&self, state: &mut State<'a, '_>) -> bool {
+        use Instruction as I;
+
+        let mut frames: Vec<Frame<'a>> = Vec::new();
+        let mut calls = Vec::new();
+        let mut address = self.root;
+        loop {
+            let instruction = &self.instructions[address];
+            address += 1;
+            let success = match instruction {
+                I::End => return true,
+                I::Fail => false,
+                &I::Jump(target) => {
+                    address = target;
+                    true
+                }
+                &I::Choice(target) => {
+                    frames.push(Frame::Choice {
+                        address: target,
+                        checkpoint: state.checkpoint(),
+                        calls: calls.len(),
+                        cuts: state.cuts,
+                    });
+                    true
+                }
+                &I::Commit(target) => {
+                    if let Some(Frame::Choice {
+                        checkpoint, cuts, ..
+                    }) = frames.pop()
+                    {
+                        state.release(checkpoint);
+                        // A cut only commits the innermost 'Or' that encloses it.
+                        state.cuts = cuts;
+                    }
+                    address = target;
+                    true
+                }
+                I::Cut => {
+                    state.cut();
+                    true
+                }
+                &I::Call(index) => {
+                    calls.push(address);
+                    address = self.entries[index];
+                    true
+                }
+                I::Return => match calls.pop() {
+                    Some(target) => {
+                        address = target;
+                        true
+                    }
+                    None => return true,
+                },
+                I::Rule(rule) => {
+                    frames.push(Frame::Rule {
+                        rule: rule.clone(),
+                        index: state.index,
+                    });
+                    true
+                }
+                I::Pop => {
+                    if let Some(checkpoint) = frames.pop().and_then(Frame::checkpoint) {
+                        state.release(checkpoint);
+                    }
+                    true
+                }
+                &I::Grow(index) => {
+                    let key = (index, state.index);
+                    let seed = state.memo.borrow_mut().seeds.get_mut(&key).map(|seed| {
+                        seed.2 = true;
+                        (seed.0.clone(), seed.1)
+                    });
+                    match seed {
+                        Some((Some(effect), depth)) => {
+                            let shift = state.values[state.depth] - depth;
+                            state.replay(effect, shift);
+                            // Skip the 'Call' and the 'Grown'.
+                            address += 2;
+                            true
+                        }
+                        Some((None, _)) => false,
+                        None => {
+                            let depth = state.values[state.depth];
+                            state
+                                .memo
+                                .borrow_mut()
+                                .seeds
+                                .insert(key, (None, depth, false));
+                            let start = Lengths::of(state);
+                            let lows = mem::replace(&mut state.lows, start);
+                            frames.push(Frame::Grow(Box::new(Grow {
+                                key,
+                                address,
+                                origin: state.checkpoint(),
+                                start,
+                                lows,
+                                calls: calls.len(),
+                                cuts: state.cuts,
+                                best: None,
+                            })));
+                            true
+                        }
+                    }
+                }
+                I::Grown => {
+                    if let Some(Frame::Grow(grow)) = frames.pop() {
+                        let Grow {
+                            key,
+                            address: call,
+                            origin,
+                            start,
+                            lows,
+                            calls: length,
+                            cuts,
+                            best,
+                        } = *grow;
+                        let detected = state.memo.borrow().seeds[&key].2;
+                        match best {
+                            Some((effect, index)) if state.index <= index => {
+                                // Backtrack to the best effect.
+                                state.restore(&origin);
+                                state.release(origin);
+                                state.replay(effect, 0);
+                                state.memo.borrow_mut().seeds.remove(&key);
+                                state.lows = lows.min(state.lows);
+                            }
+                            _ if state.lows == start && detected => {
+                                let effect = state.capture(start, cuts);
+                                if let Some(seed) = state.memo.borrow_mut().seeds.get_mut(&key) {
+                                    seed.0 = Some(effect.clone());
+                                }
+                                let best = Some((effect, state.index));
+                                state.restore(&origin);
+                                frames.push(Frame::Grow(Box::new(Grow {
+                                    key,
+                                    address: call,
+                                    origin,
+                                    start,
+                                    lows,
+                                    calls: length,
+                                    cuts,
+                                    best,
+                                })));
+                                address = call;
+                            }
+                            _ => {
+                                state.release(origin);
+                                state.memo.borrow_mut().seeds.remove(&key);
+                                state.lows = lows.min(state.lows);
+                            }
+                        }
+                    }
+                    true
+                }
+                &I::Recover { sync, end } => {
+                    let failure = state.failure.replace((state.index, Vec::new()));
+                    frames.push(Frame::Recover {
+                        sync,
+                        end,
+                        checkpoint: state.checkpoint(),
+                        failure,
+                        calls: calls.len(),
+                        cuts: state.cuts,
+                    });
+                    true
+                }
+                I::Recovered => {
+                    if let Some(Frame::Recover {
+                        end,
+                        checkpoint,
+                        failure,
+                        cuts,
+                        ..
+                    }) = frames.pop()
+                    {
+                        state.release(checkpoint);
+                        // Cuts do not escape a recovery.
+                        state.cuts = cuts;
+                        let (index, expected) = state.failure.replace(failure);
+                        for expect in expected {
+                            state.expect(index, || expect);
+                        }
+                        address = end;
+                    }
+                    true
+                }
+                I::Synced => {
+                    if let Some(Frame::Sync {
+                        end,
+                        checkpoint,
+                        start,
+                        error,
+                        failure,
+                        cuts,
+                        ..
+                    }) = frames.pop()
+                    {
+                        state.restore(&checkpoint);
+                        state.release(checkpoint);
+                        state.cuts = cuts;
+                        state.failure.replace(failure);
+                        if state.index == start {
+                            // Nothing was skipped such that the recovery would not progress.
+                            false
+                        } else {
+                            state.recovered(start, error.0, error.1);
+                            address = end;
+                            true
+                        }
+                    } else {
+                        true
+                    }
+                }
+                &I::Peek(end) => {
+                    frames.push(Frame::Look {
+                        negate: false,
+                        end,
+                        checkpoint: state.checkpoint(),
+                        failure: None,
+                        calls: calls.len(),
+                        cuts: state.cuts,
+                    });
+                    true
+                }
+                &I::Not(end) => {
+                    frames.push(Frame::Look {
+                        negate: true,
+                        end,
+                        checkpoint: state.checkpoint(),
+                        failure: Some(state.failure.take()),
+                        calls: calls.len(),
+                        cuts: state.cuts,
+                    });
+                    true
+                }
+                I::Looked => match frames.pop() {
+                    Some(Frame::Look {
+                        negate,
+                        end,
+                        checkpoint,
+                        failure,
+                        cuts,
+                        ..
+                    }) => {
+                        state.restore(&checkpoint);
+                        state.release(checkpoint);
+                        state.cuts = cuts;
+                        if let Some(failure) = failure {
+                            state.failure.replace(failure);
+                        }
+                        address = end;
+                        !negate
+                    }
+                    _ => true,
+                },
+                &I::Symbol(symbol) => state.symbol(symbol),
+                I::Text(text) => state.text(text),
+                I::Switch(keys, targets) => {
+                    let symbol = state.text[state.index..].chars().next();
+                    match symbol.and_then(|symbol| Some((symbol, class::search(keys, symbol)?))) {
+                        Some((symbol, index)) => {
+                            state.index += symbol.len_utf8();
+                            address = targets[index];
+                            true
+                        }
+                        None => {
+                            state.expect_ranges(keys);
+                            false
+                        }
+                    }
+                }
+                I::Class(ranges, negate) => state.class(ranges, *negate),
+                I::Spawn(shift, kind) => state.spawn(kind, *shift),
+                &I::Store(shift, stack) => state.store(shift, stack),
+                I::Capture(name) => state.name(name),
+                I::Back(name) => state.back(name),
+                I::Label(name) => state.label(name),
+                &I::Field(ref name, stack) => state.field(name, stack),
+                &I::Precede(precedence, bind, stack) => state.precede(precedence, bind, stack),
+                I::Set(index, set) => {
+                    state.set(*index, set.apply(state.values[*index], &state.values));
+                    true
+                }
+                &I::If(left, compare, right) => {
+                    compare.compare(state.values[left], state.values[right])
+                }
+                &I::Save(index) => {
+                    state.push(index);
+                    true
+                }
+                &I::Restore(index) => state.pop(index),
+                &I::Integer(index, radix) => state.integer(index, radix),
+                &I::Times(index, end) => {
+                    match state.values[index] {
+                        count if count > 0 => frames.push(Frame::Times(count)),
+                        _ => address = end,
+                    }
+                    true
+                }
+                &I::Again(start) => {
+                    if let Some(Frame::Times(count)) = frames.pop() {
+                        if count > 1 {
+                            frames.push(Frame::Times(count - 1));
+                            address = start;
+                        }
+                    }
+                    true
+                }
+                I::Native(indices, predicate) => state.native(indices, predicate),
+            };
+
+            if !success {
+                match Self::unwind(state, &mut frames, &mut calls) {
+                    Some(target) => address = target,
+                    None => return false,
+                }
+            }
+        }
+    }
+
+    /// Pops the frames until one of them resumes the parse and returns the address to resume at.
+    fn unwind<'a>(
+        state: &mut State<'a, '_>,
+        frames: &mut Vec<Frame<'a>>,
+        calls: &mut Vec<usize>,
+    ) -> Option<usize> {
+        while let Some(frame) = frames.pop() {
+            match frame {
+                Frame::Choice {
+                    cuts, checkpoint, ..
+                } if cuts < state.cuts => {
+                    state.release(checkpoint);
+                    state.cuts = cuts;
+                }
+                Frame::Choice {
+                    address,
+                    checkpoint,
+                    calls: length,
+                    ..
+                } => {
+                    state.restore(&checkpoint);
+                    state.release(checkpoint);
+                    calls.truncate(length);
+                    return Some(address);
+                }
+                Frame::Rule { rule, index } => state.expect(index, || Expect::Rule(rule)),
+                Frame::Times(_) => {}
+                Frame::Look {
+                    negate,
+                    end,
+                    checkpoint,
+                    failure,
+                    calls: length,
+                    cuts,
+                } => {
+                    state.restore(&checkpoint);
+                    state.release(checkpoint);
+                    state.cuts = cuts;
+                    if let Some(failure) = failure {
+                        state.failure.replace(failure);
+                    }
+                    if negate {
+                        calls.truncate(length);
+                        return Some(end);
+                    }
+                }
+                Frame::Grow(grow) => {
+                    let Grow {
+                        key,
+                        address,
+                        origin,
+                        lows,
+                        calls: length,
+                        best,
+                        ..
+                    } = *grow;
+                    state.memo.borrow_mut().seeds.remove(&key);
+                    if let Some((effect, _)) = best {
+                        // Backtrack to the best effect.
+                        state.restore(&origin);
+                        state.release(origin);
+                        state.replay(effect, 0);
+                        state.lows = lows.min(state.lows);
+                        calls.truncate(length);
+                        // Skip the 'Call' and the 'Grown'.
+                        return Some(address + 2);
+                    }
+                    state.release(origin);
+                    state.lows = lows.min(state.lows);
+                }
+                Frame::Recover {
+                    sync,
+                    end,
+                    checkpoint,
+                    failure,
+                    calls: length,
+                    cuts,
+                } => {
+                    let (index, expected) = state.failure.replace(failure);
+                    for expect in expected.iter() {
+                        state.expect(index, || expect.clone());
+                    }
+                    state.restore(&checkpoint);
+                    state.release(checkpoint);
+                    state.cuts = cuts;
+                    calls.truncate(length);
+
+                    // Skip symbols until 'sync' succeeds without recording its failures. At the end of
+                    // the text, nothing can be skipped and the recovery fails.
+                    if state.index < state.text.len() {
+                        frames.push(Frame::Sync {
+                            sync,
+                            end,
+                            checkpoint: state.checkpoint(),
+                            start: state.index,
+                            error: (index, expected),
+                            failure: state.failure.take(),
+                            calls: length,
+                            cuts,
+                        });
+                        return Some(sync);
+                    }
+                }
+                Frame::Sync {
+                    sync,
+                    end,
+                    checkpoint,
+                    start,
+                    error,
+                    failure,
+                    calls: length,
+                    cuts,
+                } => {
+                    state.restore(&checkpoint);
+                    state.release(checkpoint);
+                    state.cuts = cuts;
+                    calls.truncate(length);
+                    state.skip();
+                    if state.index < state.text.len() {
+                        frames.push(Frame::Sync {
+                            sync,
+                            end,
+                            checkpoint: state.checkpoint(),
+                            start,
+                            error,
+                            failure,
+                            calls: length,
+                            cuts,
+                        });
+                        return Some(sync);
+                    }
+                    state.failure.replace(failure);
+                    state.recovered(start, error.0, error.1);
+                    return Some(end);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl From<Node> for Program {
+    fn from(node: Node) -> Program {
+        struct State {
+            instructions: Vec<Instruction>,
+            rules: HashMap<usize, String>,
+            recursive: HashSet<usize>,
+            /// Whether there are cuts, in which case the last alternative of an 'Or' also has a choice
+            /// such that the cuts that it encloses are reset when it ends.
+            cuts: bool,
+        }
+
+        fn cuts(node: &Node) -> bool {
+            match node {
+                Cut => true,
+                And(left, right) | Or(left, right) | Recover(left, right) => {
+                    cuts(left) || cuts(right)
+                }
+                Define(_, node) | Shift(_, node) | Times(_, node) | Peek(node) | Not(node) => {
+                    cuts(node)
+                }
+                Switch(cases) => cases.iter().any(|case| cuts(&case.1)),
+                _ => false,
+            }
+        }
+
+        fn next(node: &Node, state: &mut State) {
+            use Instruction as I;
+
+            fn emit(instruction: Instruction, state: &mut State) -> usize {
+                state.instructions.push(instruction);
+                state.instructions.len() - 1
+            }
+
+            match node {
+                True => {}
+                False => {
+                    emit(I::Fail, state);
+                }
+                And(_, _) => {
+                    for node in node.flatten() {
+                        next(node, state);
+                    }
+                }
+                Or(_, _) => {
+                    let nodes = node.flatten();
+                    let mut commits = Vec::new();
+                    for (i, node) in nodes.iter().enumerate() {
+                        if i + 1 < nodes.len() || state.cuts {
+                            let choice = emit(I::Choice(0), state);
+                            next(node, state);
+                            commits.push(emit(I::Commit(0), state));
+                            state.instructions[choice] = I::Choice(state.instructions.len());
+                        } else {
+                            next(node, state);
+                        }
+                    }
+                    if state.cuts {
+                        // The choice of the last alternative resumes here.
+                        emit(I::Fail, state);
+                    }
+                    let end = state.instructions.len();
+                    for commit in commits {
+                        state.instructions[commit] = I::Commit(end);
+                    }
+                }
+                &Refer(Index(index)) => {
+                    let rule = state.rules.get(&index).cloned();
+                    if let Some(rule) = &rule {
+                        emit(I::Rule(rule.clone()), state);
+                    }
+                    if state.recursive.contains(&index) {
+                        emit(I::Grow(index), state);
+                        emit(I::Call(index), state);
+                        emit(I::Grown, state);
+                    } else {
+                        emit(I::Call(index), state);
+                    }
+                    if rule.is_some() {
+                        emit(I::Pop, state);
+                    }
+                }
+                Cut => {
+                    emit(I::Cut, state);
+                }
+                Peek(inner) | Not(inner) => {
+                    let look = emit(I::Fail, state);
+                    next(inner, state);
+                    emit(I::Looked, state);
+                    let end = state.instructions.len();
+                    state.instructions[look] = match node {
+                        Peek(_) => I::Peek(end),
+                        _ => I::Not(end),
+                    };
+                }
+                Recover(node, sync) => {
+                    let recover = emit(I::Recover { sync: 0, end: 0 }, state);
+                    next(node, state);
+                    emit(I::Recovered, state);
+                    let sync_address = state.instructions.len();
+                    next(sync, state);
+                    emit(I::Synced, state);
+                    state.instructions[recover] = I::Recover {
+                        sync: sync_address,
+                        end: state.instructions.len(),
+                    };
+                }
+                Switch(cases) => {
+                    let switch = emit(I::Fail, state);
+                    let mut keys = Vec::new();
+                    let mut targets = Vec::new();
+                    let mut jumps = Vec::new();
+                    // Cases are laid out in order of their range such that the program is deterministic.
+                    let mut cases: Vec<_> = cases.iter().collect();
+                    cases.sort_by_key(|case| *case.0.start());
+                    for (key, node) in cases {
+                        keys.push(key.clone());
+                        targets.push(state.instructions.len());
+                        next(node, state);
+                        jumps.push(emit(I::Jump(0), state));
+                    }
+                    let end = state.instructions.len();
+                    for jump in jumps {
+                        state.instructions[jump] = I::Jump(end);
+                    }
+                    state.instructions[switch] = I::Switch(keys, targets);
+                }
+                Class(ranges, negate) => {
+                    emit(I::Class(ranges.clone(), *negate), state);
+                }
+                &Spawn(shift, ref kind) => {
+                    emit(I::Spawn(shift, kind.clone()), state);
+                }
+                &Symbol(symbol) => {
+                    emit(I::Symbol(symbol), state);
+                }
+                Text(text) => {
+                    emit(I::Text(text.clone()), state);
+                }
+                &Store(shift, stack) => {
+                    emit(I::Store(shift, stack), state);
+                }
+                Capture(name) => {
+                    emit(I::Capture(name.clone()), state);
+                }
+                Back(name) => {
+                    emit(I::Back(name.clone()), state);
+                }
+                Label(name) => {
+                    emit(I::Label(name.clone()), state);
+                }
+                &Field(ref name, stack) => {
+                    emit(I::Field(name.clone(), stack), state);
+                }
+                &Precede(precedence, bind, stack) => {
+                    emit(I::Precede(precedence, bind, stack), state);
+                }
+                Set(Index(index), set) => {
+                    emit(I::Set(*index, set.clone()), state);
+                }
+                &If(Index(left), condition, Index(right)) => {
+                    emit(I::If(left, condition, right), state);
+                }
+                &Push(Index(index)) => {
+                    emit(I::Save(index), state);
+                }
+                &Pop(Index(index)) => {
+                    emit(I::Restore(index), state);
+                }
+                &Integer(Index(index), radix) => {
+                    emit(I::Integer(index, radix), state);
+                }
+                Times(Index(index), node) => {
+                    let times = emit(I::Fail, state);
+                    next(node, state);
+                    emit(I::Again(times + 1), state);
+                    state.instructions[times] = I::Times(*index, state.instructions.len());
+                }
+                Native(slots, predicate) => {
+                    let indices = slots
+                        .iter()
+                        .map(|slot| match slot {
+                            &Index(index) => index,
+                            slot => panic!("Invalid slot '{:?}'.", slot),
+                        })
+                        .collect();
+                    emit(I::Native(indices, predicate.clone()), state);
+                }
+                node => panic!("Invalid node '{}'.", node),
+            }
+        }
+
+        let (node, nodes, rules, mut indices) = node.resolve();
+        let depth = Path(".depth".into());
+        let depth_index = match indices.get(&depth) {
+            Some(index) => *index,
+            None => {
+                let index = indices.len();
+                indices.insert(depth, index);
+                index
+            }
+        };
+
+        let mut state = State {
+            instructions: Vec::new(),
+            rules: rules
+                .into_iter()
+                .filter_map(|(identifier, index)| match identifier {
+                    Path(path) => Some((index, path)),
+                    _ => None,
+                })
+                .collect(),
+            recursive: Node::recursive(&nodes),
+            cuts: cuts(&node) || nodes.iter().any(cuts),
+        };
+        next(&node, &mut state);
+        state.instructions.push(Instruction::End);
+        let mut entries = Vec::new();
+        for node in nodes.iter() {
+            entries.push(state.instructions.len());
+            next(node, &mut state);
+            state.instructions.push(Instruction::Return);
+        }
+        Program {
+            instructions: state.instructions,
+            entries,
+            root: 0,
+            indices,
+            depth: depth_index,
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        use Instruction as I;
+
+        match self {
+            I::End => formatter.write_str("end"),
+            I::Fail => formatter.write_str("fail"),
+            I::Jump(address) => write!(formatter, "jump {}", address),
+            I::Choice(address) => write!(formatter, "choice {}", address),
+            I::Commit(address) => write!(formatter, "commit {}", address),
+            I::Cut => formatter.write_str("cut"),
+            I::Call(index) => write!(formatter, "call {}", index),
+            I::Return => formatter.write_str("return"),
+            I::Rule(rule) => write!(formatter, "rule {}", rule),
+            I::Pop => formatter.write_str("pop"),
+            I::Grow(index) => write!(formatter, "grow {}", index),
+            I::Grown => formatter.write_str("grown"),
+            I::Recover { sync, end } => write!(formatter, "recover {} {}", sync, end),
+            I::Recovered => formatter.write_str("recovered"),
+            I::Synced => formatter.write_str("synced"),
+            I::Peek(address) => write!(formatter, "peek {}", address),
+            I::Not(address) => write!(formatter, "not {}", address),
+            I::Looked => formatter.write_str("looked"),
+            I::Symbol(symbol) => write!(formatter, "symbol '{}'", symbol.escape_debug()),
+            I::Text(text) => write!(formatter, "text \"{}\"", text.escape_debug()),
+            I::Switch(keys, targets) => {
+                formatter.write_str("switch {")?;
+                for (index, (key, target)) in keys.iter().zip(targets).enumerate() {
+                    if index > 0 {
+                        formatter.write_str(", ")?;
+                    }
+                    class::write(key, formatter)?;
+                    write!(formatter, ": {}", target)?;
+                }
+                formatter.write_str("}")
+            }
+            I::Class(ranges, negate) => {
+                formatter.write_str(if *negate { "class [^" } else { "class [" })?;
+                for (index, range) in ranges.iter().enumerate() {
+                    if index > 0 {
+                        formatter.write_str(", ")?;
+                    }
+                    class::write(range, formatter)?;
+                }
+                formatter.write_str("]")
+            }
+            I::Spawn(shift, kind) => write!(formatter, "spawn {} {}", kind, shift),
+            I::Store(shift, Stack::Push) => write!(formatter, "store push {}", shift),
+            I::Store(shift, Stack::Pop) => write!(formatter, "store pop {}", shift),
+            I::Capture(name) => write!(formatter, "capture {}", name),
+            I::Back(name) => write!(formatter, "back {}", name),
+            I::Label(name) => write!(formatter, "label {}", name),
+            I::Field(name, Stack::Push) => write!(formatter, "field push {}", name),
+            I::Field(name, Stack::Pop) => write!(formatter, "field pop {}", name),
+            I::Precede(precedence, bind, Stack::Push) => {
+                write!(formatter, "precede push {} {:?}", precedence, bind)
+            }
+            I::Precede(precedence, bind, Stack::Pop) => {
+                write!(formatter, "precede pop {} {:?}", precedence, bind)
+            }
+            I::Set(index, Set::Value(value)) => write!(formatter, "set {} = {}", index, value),
+            I::Set(index, Set::Add(value)) => write!(formatter, "set {} += {}", index, value),
+            I::Set(index, Set::Multiply(value)) => write!(formatter, "set {} *= {}", index, value),
+            I::Set(index, Set::Copy(copy)) => write!(formatter, "set {} = {:?}", index, copy),
+            I::Set(index, Set::Plus(copy)) => write!(formatter, "set {} += {:?}", index, copy),
+            I::Set(index, Set::Minus(copy)) => write!(formatter, "set {} -= {:?}", index, copy),
+            I::Set(index, Set::Map(_)) => write!(formatter, "set {} = map", index),
+            I::If(left, compare, right) => {
+                let compare = match compare {
+                    If::Less => "<",
+                    If::LessEqual => "<=",
+                    If::Equal => "==",
+                    If::NotEqual => "!=",
+                    If::Greater => ">",
+                    If::GreaterEqual => ">=",
+                };
+                write!(formatter, "if {} {} {}", left, compare, right)
+            }
+            I::Save(index) => write!(formatter, "save {}", index),
+            I::Restore(index) => write!(formatter, "restore {}", index),
+            I::Integer(index, radix) => write!(formatter, "integer {} {}", index, radix),
+            I::Times(index, address) => write!(formatter, "times {} {}", index, address),
+            I::Again(address) => write!(formatter, "again {}", address),
+            I::Native(indices, _) => write!(formatter, "native {:?}", indices),
+        }
+    }
+}
+
+/// Dumps the instructions with their address where the entry of each reference is labeled.
+impl Display for Program {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        for (address, instruction) in self.instructions.iter().enumerate() {
+            if address == self.root {
+                writeln!(formatter, "root:")?;
+            }
+            for (index, _) in self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| **entry == address)
+            {
+                writeln!(formatter, "reference {}:", index)?;
+            }
+            writeln!(formatter, "{:>6}  {}", address, instruction)?;
+        }
+        Ok(())
+    }
+}
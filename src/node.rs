@@ -1,13 +1,15 @@
+use crate::class;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::fmt::{Display, Error, Formatter};
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::mem;
 use std::ops::Range;
 use std::ops::RangeInclusive;
 use std::ops::{Bound, RangeBounds};
 use std::str;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use Identifier::*;
 use Node::*;
 
@@ -31,18 +33,61 @@ pub enum Stack {
     Pop,
 }
 
+// Maps are compared by address, which at worst keeps apart nodes that could have been shared.
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Set {
     Value(isize),
     Add(isize),
+    Multiply(isize),
     Copy(Identifier),
-    // Map(fn(isize) -> isize), // Is this a good idea?
+    /// Adds the value of the slot.
+    Plus(Identifier),
+    /// Subtracts the value of the slot.
+    Minus(Identifier),
+    Map(fn(isize) -> isize),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum If {
     Less,
+    LessEqual,
     Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// A user function over the text that remains to be parsed and the values of some slots. It returns the
+/// length in bytes of the text that it consumes or 'None' if it fails.
+#[derive(Clone)]
+pub struct Predicate(pub Arc<Function>);
+
+pub type Function = dyn Fn(&str, &[isize]) -> Option<usize> + Send + Sync;
+
+// Predicates are compared by address, like the 'Set::Map' functions.
+impl PartialEq for Predicate {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Predicate {}
+
+impl Hash for Predicate {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Arc::as_ptr(&self.0) as *const () as usize).hash(state)
+    }
+}
+
+impl Debug for Predicate {
+    fn fmt(&self, formatter: &mut Formatter) -> Result<(), Error> {
+        write!(
+            formatter,
+            "Predicate({:p})",
+            Arc::as_ptr(&self.0) as *const ()
+        )
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -56,54 +101,55 @@ pub enum Node {
 
     Symbol(char),
     Text(String),
-    Switch(Vec<(char, Node)>),
+    Switch(Vec<(RangeInclusive<char>, Node)>),
+    /// Sorted and disjoint character ranges where the class is negated if the flag is set.
+    Class(Vec<RangeInclusive<char>>, bool),
 
     Shift(usize, Box<Self>),
-    Spawn(String),
+    Spawn(usize, String),
     Depth(isize),
     Precede(usize, Bind, Stack),
     Store(usize, Stack),
+    /// Names the text of the last store.
+    Capture(String),
+    /// Parses the text of the last capture with the name that is in scope.
+    Back(String),
+    /// Names the last store as a value of the syntax that adopts it.
+    Label(String),
+    /// A push marks the number of trees, and the matching pop names the last tree as a child of the syntax
+    /// that adopts it if a tree was pushed since the mark.
+    Field(String, Stack),
 
     Set(Identifier, Set),
     If(Identifier, If, Identifier),
+    /// Saves the value of the slot such that a later 'Pop' of the same slot restores it.
+    Push(Identifier),
+    Pop(Identifier),
+    /// Parses the digits of an integer in the radix into the slot.
+    Integer(Identifier, u32),
+    /// Parses the node as many times as the value of the slot when it starts, or not at all if it is not
+    /// positive.
+    Times(Identifier, Box<Self>),
+    /// Calls the predicate with the values of the slots.
+    Native(Vec<Identifier>, Predicate),
+
+    Recover(Box<Self>, Box<Self>),
+    Cut,
+    Peek(Box<Self>),
+    Not(Box<Self>),
     /*
-    State nodes:
-    Push(),
-    Pop(),
-
     - store: Store(usize, Stack) -> Shift(usize, Store(Stack))
     - depth: Depth(isize) -> Set(Path(".depth"), Add(isize))
     - if-else:
         Or(And(If("left", compare, "right"), if), else)
-    - indent:
-        And(
-            Set("indent", Value(0)),
-            Define(0, Or(
-                And(Symbol('\t'), Set("indent", Add(1)), Refer(0)),
-                True
-            )),
-            Refer(0),
-            If("indent", >, "$.indent"),
-            Set(".indent", Copy("index")),
-        )
-    - dedent:
-        And(
-            Set("indent", Value(0)),
-            Loop(And(
-                Symbol('\t'),
-                Set("indent", Add(1))
-            )),
-            If("indent", <, ".indent"),
-            Set(".index", Copy("index")),
-        )
     - precedence:
         And(
             Set("precedence", Value(precedence * 2)),
             If("precedence", >, ".precedence"),
-            Push(),
+            Push(".precedence"),
             Set(".precedence", Value(Bind::Left => precedence * 2, Bind::Right => precedence * 2 - 1)),
             node,
-            Pop(),
+            Pop(".precedence"),
         )
     */
 }
@@ -170,6 +216,36 @@ impl ToNode for RangeInclusive<char> {
     }
 }
 
+impl Set {
+    /// Computes the new 'value' of a slot where the identifiers must be resolved indices in 'values'.
+    /// The arithmetic saturates since the values may come from the parsed text.
+    pub fn apply(&self, value: isize, values: &[isize]) -> isize {
+        match self {
+            Set::Value(set) => *set,
+            Set::Add(add) => value.saturating_add(*add),
+            Set::Multiply(multiply) => value.saturating_mul(*multiply),
+            &Set::Copy(Index(index)) => values[index],
+            &Set::Plus(Index(index)) => value.saturating_add(values[index]),
+            &Set::Minus(Index(index)) => value.saturating_sub(values[index]),
+            Set::Map(map) => map(value),
+            set => panic!("Invalid set '{:?}'.", set),
+        }
+    }
+}
+
+impl If {
+    pub fn compare(self, left: isize, right: isize) -> bool {
+        match self {
+            If::Less => left < right,
+            If::LessEqual => left <= right,
+            If::Equal => left == right,
+            If::NotEqual => left != right,
+            If::Greater => left > right,
+            If::GreaterEqual => left >= right,
+        }
+    }
+}
+
 impl Node {
     pub fn unique() -> usize {
         static COUNTER: AtomicUsize = AtomicUsize::new(0);
@@ -181,7 +257,9 @@ impl Node {
             And(left, right) => left.count() + right.count() + 1,
             Or(left, right) => left.count() + right.count() + 1,
             Define(_, node) => node.count() + 1,
-            Shift(_, node) => node.count() + 1,
+            Shift(_, node) | Times(_, node) => node.count() + 1,
+            Recover(node, sync) => node.count() + sync.count() + 1,
+            Peek(node) | Not(node) => node.count() + 1,
             Switch(cases) => cases
                 .iter()
                 .fold(1, |count, case| count + case.1.count() + 1),
@@ -210,6 +288,23 @@ impl Node {
                 *node = map(*node);
                 Shift(shift, node)
             }
+            Times(identifier, mut node) => {
+                *node = map(*node);
+                Times(identifier, node)
+            }
+            Recover(mut node, mut sync) => {
+                *node = map(*node);
+                *sync = map(*sync);
+                Recover(node, sync)
+            }
+            Peek(mut node) => {
+                *node = map(*node);
+                Peek(node)
+            }
+            Not(mut node) => {
+                *node = map(*node);
+                Not(node)
+            }
             Switch(mut cases) => {
                 for case in cases.iter_mut() {
                     let value = mem::replace(&mut case.1, True);
@@ -261,6 +356,63 @@ impl Node {
         nodes
     }
 
+    /// Indices of the 'nodes' that may refer to themselves before consuming any text.
+    /// References are assumed to not consume text such that the result is conservative.
+    pub fn recursive(nodes: &[Node]) -> HashSet<usize> {
+        fn nullable(node: &Node) -> bool {
+            match node {
+                False | Symbol(_) | Class(_, _) | Integer(_, _) => false,
+                Text(text) => text.is_empty(),
+                Switch(cases) => cases.is_empty(),
+                And(left, right) => nullable(left) && nullable(right),
+                Or(left, right) => nullable(left) || nullable(right),
+                Shift(_, node) => nullable(node),
+                _ => true,
+            }
+        }
+
+        fn lefts(node: &Node, indices: &mut Vec<usize>) {
+            match node {
+                &Refer(Index(index)) => indices.push(index),
+                And(left, right) => {
+                    lefts(left, indices);
+                    if nullable(left) {
+                        lefts(right, indices);
+                    }
+                }
+                Or(left, right) | Recover(left, right) => {
+                    lefts(left, indices);
+                    lefts(right, indices);
+                }
+                Shift(_, node) | Times(_, node) | Peek(node) | Not(node) => lefts(node, indices),
+                _ => {}
+            }
+        }
+
+        let edges: Vec<_> = nodes
+            .iter()
+            .map(|node| {
+                let mut indices = Vec::new();
+                lefts(node, &mut indices);
+                indices
+            })
+            .collect();
+        let mut recursive = HashSet::new();
+        for index in 0..nodes.len() {
+            let mut visited = HashSet::new();
+            let mut pending = edges[index].clone();
+            while let Some(current) = pending.pop() {
+                if current == index {
+                    recursive.insert(index);
+                    break;
+                } else if current < edges.len() && visited.insert(current) {
+                    pending.extend(edges[current].iter().cloned());
+                }
+            }
+        }
+        recursive
+    }
+
     pub fn resolve(
         self,
     ) -> (
@@ -268,6 +420,31 @@ impl Node {
         Vec<Node>,
         HashMap<Identifier, usize>,
         HashMap<Identifier, usize>,
+    ) {
+        self.transform(true)
+    }
+
+    /// Normalizes the node and replaces its definitions, references and slots with indices like 'resolve'
+    /// does, without optimizing the nodes.
+    pub fn identify(
+        self,
+    ) -> (
+        Node,
+        Vec<Node>,
+        HashMap<Identifier, usize>,
+        HashMap<Identifier, usize>,
+    ) {
+        self.transform(false)
+    }
+
+    fn transform(
+        self,
+        full: bool,
+    ) -> (
+        Node,
+        Vec<Node>,
+        HashMap<Identifier, usize>,
+        HashMap<Identifier, usize>,
     ) {
         struct State {
             nodes: Vec<Option<Node>>,
@@ -276,6 +453,8 @@ impl Node {
             value_indices: HashMap<Identifier, usize>,
             refer_threshold: usize,
             optimize: HashSet<usize>,
+            recursive: HashSet<usize>,
+            scopes: HashSet<usize>,
         }
 
         /*
@@ -385,7 +564,26 @@ impl Node {
                     compare,
                     Index(value_index(right, state)),
                 ),
-                Set(target, value) => Set(Index(value_index(target, state)), value),
+                Set(target, value) => {
+                    let value = match value {
+                        Set::Copy(source) => Set::Copy(Index(value_index(source, state))),
+                        Set::Plus(source) => Set::Plus(Index(value_index(source, state))),
+                        Set::Minus(source) => Set::Minus(Index(value_index(source, state))),
+                        value => value,
+                    };
+                    Set(Index(value_index(target, state)), value)
+                }
+                Push(identifier) => Push(Index(value_index(identifier, state))),
+                Integer(identifier, radix) => Integer(Index(value_index(identifier, state)), radix),
+                Times(identifier, node) => Times(Index(value_index(identifier, state)), node),
+                Pop(identifier) => Pop(Index(value_index(identifier, state))),
+                Native(slots, predicate) => Native(
+                    slots
+                        .into_iter()
+                        .map(|slot| Index(value_index(slot, state)))
+                        .collect(),
+                    predicate,
+                ),
                 node => node,
             }
         }
@@ -393,6 +591,13 @@ impl Node {
         fn expand(node: Node, state: &mut State) -> Node {
             fn next(node: Node, state: &mut State) -> Node {
                 match node {
+                    // Left recursive references are kept such that the parser can grow them.
+                    Refer(Index(index))
+                        if state.recursive.contains(&index) || state.scopes.contains(&index) =>
+                    {
+                        update(index, state);
+                        Refer(Index(index))
+                    }
                     Refer(Index(index)) => {
                         update(index, state);
                         state.nodes[index].clone().unwrap_or(False)
@@ -404,6 +609,48 @@ impl Node {
             next(node, state)
         }
 
+        /// A cut commits the innermost 'Or' that encloses it, such that the alternatives of an 'Or'
+        /// that holds a cut, directly or through the references it starts with, are kept as references
+        /// that are not factored or merged with other choices.
+        fn scope(node: Node, state: &mut State) -> Node {
+            fn cuts(node: &Node, nodes: &[Option<Node>], visited: &mut HashSet<usize>) -> bool {
+                match node {
+                    Cut => true,
+                    And(left, right) => cuts(left, nodes, visited) || cuts(right, nodes, visited),
+                    Shift(_, node) | Times(_, node) | Peek(node) | Not(node) => {
+                        cuts(node, nodes, visited)
+                    }
+                    // A rule that refers to itself is visited once.
+                    &Refer(Index(index)) => match nodes.get(index) {
+                        Some(Some(node)) if visited.insert(index) => cuts(node, nodes, visited),
+                        _ => false,
+                    },
+                    _ => false,
+                }
+            }
+
+            match node {
+                Or(_, _) => {
+                    let nodes: Vec<_> = node.flatten().into_iter().cloned().collect();
+                    let scoped = nodes
+                        .iter()
+                        .any(|node| cuts(node, &state.nodes, &mut HashSet::new()));
+                    any(nodes
+                        .into_iter()
+                        .map(|node| match scope(node, state) {
+                            node if scoped => {
+                                let index = define(Unique(Node::unique()), node, state);
+                                state.scopes.insert(index);
+                                Refer(Index(index))
+                            }
+                            node => node,
+                        })
+                        .collect())
+                }
+                node => node.map(|node| scope(node, state)),
+            }
+        }
+
         /// (a & b) | (a & c) => a & (b | c)
         fn factor_left(node: Node) -> Node {
             match node {
@@ -451,7 +698,8 @@ impl Node {
         }
 
         /// True & a => a, a & True => a, False & a => False, a & False => False,
-        /// a | a => a, False | a => a, a | False => a, True | a => a | True
+        /// a | a => a, False | a => a, a | False => a, True | a => a | True,
+        /// &True => True, &False => False, !True => False, !False => True, &&a => &a, !!a => &a, !&a => !a
         fn boolean(node: Node) -> Node {
             match node {
                 And(left, right) => match (*left, *right) {
@@ -468,6 +716,20 @@ impl Node {
                     (True, right) => or(right, True),
                     (left, right) => or(left, right),
                 },
+                Peek(node) => match *node {
+                    True => True,
+                    False => False,
+                    Peek(node) => Peek(node),
+                    Not(node) => Not(node),
+                    node => peek(node),
+                },
+                Not(node) => match *node {
+                    True => False,
+                    False => True,
+                    Peek(node) => Not(node),
+                    Not(node) => Peek(node),
+                    node => not(node),
+                },
                 node => node,
             }
         }
@@ -496,11 +758,16 @@ impl Node {
                     ))),
                     (left, right) => and(left, right),
                 },
-                Spawn(kind) => Shift(0, Spawn(kind).into()),
+                Spawn(shift, kind) => Shift(0, Spawn(shift, kind).into()),
                 Depth(depth) => Shift(0, Depth(depth).into()),
                 Set(target, value) => Shift(0, Set(target, value).into()),
                 If(left, compare, right) => Shift(0, If(left, compare, right).into()),
+                Push(identifier) => Shift(0, Push(identifier).into()),
+                Pop(identifier) => Shift(0, Pop(identifier).into()),
                 Store(shift, stack) => Shift(0, Store(shift, stack).into()),
+                Capture(name) => Shift(0, Capture(name).into()),
+                Label(name) => Shift(0, Label(name).into()),
+                Field(name, stack) => Shift(0, Field(name, stack).into()),
                 Precede(precedence, bind, stack) => {
                     Shift(0, Precede(precedence, bind, stack).into())
                 }
@@ -512,6 +779,7 @@ impl Node {
             match node {
                 Shift(shift, node) => match *node {
                     Store(inner, action) => Store(shift + inner, action),
+                    Spawn(inner, kind) => Spawn(shift + inner, kind),
                     node => node,
                 },
                 node => node,
@@ -532,14 +800,26 @@ impl Node {
             }
         }
 
-        /// 'a' => { 'a': True }, "ab" => { 'a': True } & { 'b': True }
+        /// 'a' => { 'a': True }, "ab" => { 'a': True } & { 'b': True }, ['a'..='z'] => { 'a'..='z': True }
         fn pre(node: Node) -> Node {
             match node {
                 Text(text) => all(text
                     .chars()
-                    .map(|symbol| Switch(vec![(symbol, True)]))
+                    .map(|symbol| Switch(vec![(symbol..=symbol, True)]))
                     .collect()),
-                Symbol(symbol) => Switch(vec![(symbol, True)]),
+                Symbol(symbol) => Switch(vec![(symbol..=symbol, True)]),
+                Class(ranges, negate) => {
+                    let ranges = if negate {
+                        class::complement(&ranges)
+                    } else {
+                        ranges
+                    };
+                    if ranges.is_empty() {
+                        False
+                    } else {
+                        Switch(ranges.into_iter().map(|range| (range, True)).collect())
+                    }
+                }
                 node => node,
             }
         }
@@ -595,23 +875,25 @@ impl Node {
                     (left, right) => or(left, right),
                 },
                 Switch(cases) if cases.len() == 0 => True,
-                Switch(mut cases) => {
-                    let mut map = HashMap::new();
-                    for case in cases.drain(..) {
-                        match map.get_mut(&case.0) {
-                            Some(value) => {
-                                *value = process(or(mem::replace(value, True), case.1), state);
-                            }
-                            None => {
-                                map.insert(case.0, case.1);
-                            }
+                Switch(cases) => {
+                    // Overlapping cases are split in disjoint range buckets that try the cases
+                    // that contain them in order.
+                    let ranges: Vec<_> = cases.iter().map(|case| case.0.clone()).collect();
+                    let mut buckets = Vec::new();
+                    for (range, indices) in class::buckets(&ranges) {
+                        let node = match indices.as_slice() {
+                            [index] => cases[*index].1.clone(),
+                            indices => process(
+                                any(indices
+                                    .iter()
+                                    .map(|index| cases[*index].1.clone())
+                                    .collect()),
+                                state,
+                            ),
                         };
+                        buckets.push((range, node));
                     }
-
-                    for (key, value) in map {
-                        cases.push((key, value));
-                    }
-                    Switch(cases)
+                    Switch(buckets)
                 }
                 Text(text) if text.len() == 0 => True,
                 node => node,
@@ -637,8 +919,12 @@ impl Node {
                     (left, right) => and(left, right),
                 },
                 Switch(mut cases) if cases.len() == 1 => {
-                    let case = cases.pop().unwrap();
-                    post(and(Text(case.0.into()), case.1))
+                    let (range, node) = cases.pop().unwrap();
+                    if range.start() == range.end() {
+                        post(and(Text(range.start().to_string()), node))
+                    } else {
+                        and(Class(vec![range], false), node)
+                    }
                 }
                 Symbol(symbol) => Text(symbol.into()),
                 node => node,
@@ -685,11 +971,34 @@ impl Node {
             value_indices: HashMap::new(),
             refer_threshold: 1024,
             optimize: HashSet::new(),
+            recursive: HashSet::new(),
+            scopes: HashSet::new(),
         };
         print("ORIGINAL", &self, &state);
         let node = self
             .descend(normalize)
             .descend(|node| identify(node, &mut state));
+        if !full {
+            let nodes = state
+                .nodes
+                .into_iter()
+                .map(|node| node.unwrap_or(False))
+                .collect();
+            return (node, nodes, state.node_indices, state.value_indices);
+        }
+        let node = scope(node, &mut state);
+        for index in 0..state.nodes.len() {
+            // The node is cloned such that the cuts of the references to it are still found.
+            if let Some(node) = state.nodes[index].clone() {
+                state.nodes[index] = Some(scope(node, &mut state));
+            }
+        }
+        let nodes: Vec<_> = state
+            .nodes
+            .iter()
+            .map(|node| node.clone().unwrap_or(False))
+            .collect();
+        state.recursive = Node::recursive(&nodes);
         // print("IDENTIFY", &node, &state);
         let node = optimize(node, &mut state);
         for i in 0..state.nodes.len() {
@@ -767,24 +1076,62 @@ impl Display for Node {
                     if mem::replace(&mut separate, true) {
                         formatter.write_str(", ")?;
                     }
-                    formatter.write_str("'")?;
-                    Display::fmt(&case.0.escape_debug(), formatter)?;
-                    formatter.write_str("'")?;
+                    class::write(&case.0, formatter)?;
                     formatter.write_str(": ")?;
                     Display::fmt(&case.1, formatter)?;
                 }
                 formatter.write_str("}")
             }
-            Spawn(kind) => {
+            Class(ranges, negate) => {
+                let mut separate = false;
+                formatter.write_str(if *negate { "[^" } else { "[" })?;
+                for range in ranges {
+                    if mem::replace(&mut separate, true) {
+                        formatter.write_str(", ")?;
+                    }
+                    class::write(range, formatter)?;
+                }
+                formatter.write_str("]")
+            }
+            Spawn(shift, kind) => {
                 formatter.write_str("[")?;
                 Display::fmt(kind, formatter)?;
-                formatter.write_str("]")
+                formatter.write_str("]")?;
+                formatter.write_str("(")?;
+                Display::fmt(shift, formatter)?;
+                formatter.write_str(")")
             }
             Depth(depth) => {
                 formatter.write_str("D(")?;
                 Display::fmt(depth, formatter)?;
                 formatter.write_str(")")
             }
+            Capture(name) => {
+                formatter.write_str("Capture(")?;
+                Display::fmt(name, formatter)?;
+                formatter.write_str(")")
+            }
+            Back(name) => {
+                formatter.write_str("Back(")?;
+                Display::fmt(name, formatter)?;
+                formatter.write_str(")")
+            }
+            Label(name) => {
+                formatter.write_str("Label(")?;
+                Display::fmt(name, formatter)?;
+                formatter.write_str(")")
+            }
+            Field(name, stack) => {
+                let stack = match stack {
+                    Stack::Push => "+",
+                    Stack::Pop => "-",
+                };
+                formatter.write_str(stack)?;
+                formatter.write_str("Field(")?;
+                Display::fmt(name, formatter)?;
+                formatter.write_str(")")?;
+                formatter.write_str(stack)
+            }
             Store(shift, stack) => {
                 let stack = match stack {
                     Stack::Push => "+",
@@ -824,12 +1171,62 @@ impl Display for Node {
                 Debug::fmt(value, formatter)?;
                 formatter.write_str(")")
             }
+            Integer(identifier, radix) => {
+                formatter.write_str("Integer(")?;
+                Debug::fmt(identifier, formatter)?;
+                formatter.write_str(", ")?;
+                Display::fmt(radix, formatter)?;
+                formatter.write_str(")")
+            }
+            Times(identifier, node) => {
+                formatter.write_str("Times(")?;
+                Debug::fmt(identifier, formatter)?;
+                formatter.write_str(", ")?;
+                Display::fmt(node, formatter)?;
+                formatter.write_str(")")
+            }
+            Native(slots, predicate) => {
+                Debug::fmt(predicate, formatter)?;
+                Debug::fmt(slots, formatter)
+            }
+            Push(identifier) => {
+                formatter.write_str("Push(")?;
+                Debug::fmt(identifier, formatter)?;
+                formatter.write_str(")")
+            }
+            Pop(identifier) => {
+                formatter.write_str("Pop(")?;
+                Debug::fmt(identifier, formatter)?;
+                formatter.write_str(")")
+            }
+            Cut => formatter.write_str("Cut"),
+            Peek(node) => {
+                formatter.write_str("&(")?;
+                Display::fmt(node, formatter)?;
+                formatter.write_str(")")
+            }
+            Not(node) => {
+                formatter.write_str("!(")?;
+                Display::fmt(node, formatter)?;
+                formatter.write_str(")")
+            }
+            Recover(node, sync) => {
+                formatter.write_str("Recover(")?;
+                Display::fmt(node, formatter)?;
+                formatter.write_str(", ")?;
+                Display::fmt(sync, formatter)?;
+                formatter.write_str(")")
+            }
             If(left, compare, right) => {
                 formatter.write_str("(")?;
                 Debug::fmt(left, formatter)?;
                 formatter.write_str(match compare {
                     If::Less => " < ",
+                    If::LessEqual => " <= ",
                     If::Equal => " = ",
+                    If::NotEqual => " != ",
+                    If::Greater => " > ",
+                    If::GreaterEqual => " >= ",
                 })?;
                 Debug::fmt(right, formatter)?;
                 formatter.write_str(")")
@@ -846,6 +1243,54 @@ pub fn store(node: impl ToNode) -> Node {
     and(Store(0, Stack::Push), and(node, Store(0, Stack::Pop)))
 }
 
+/// Stores the text of 'node' and names it such that 'back' can match the same text again. A capture is
+/// in scope until the end of the syntax that contains it.
+pub fn capture(name: &str, node: impl ToNode) -> Node {
+    and(store(node), Capture(name.into()))
+}
+
+/// Parses the text of the last capture named 'name' that is in scope.
+pub fn back(name: &str) -> Node {
+    Back(name.into())
+}
+
+/// Stores the text of 'node' as the value named 'name' of the syntax that contains it. Values with the
+/// same name form a list.
+pub fn store_as(name: &str, node: impl ToNode) -> Node {
+    and(store(node), Label(name.into()))
+}
+
+/// Names the tree of the syntax that 'node' parses as the field 'name' of the syntax that contains it.
+/// Fields with the same name form a list. The name is not given if 'node' parsed no tree.
+pub fn field(name: &str, node: impl ToNode) -> Node {
+    and(
+        Field(name.into(), Stack::Push),
+        and(node, Field(name.into(), Stack::Pop)),
+    )
+}
+
+/// Parses 'node' or, if it fails, skips text until 'sync' would succeed and produces an 'error' tree.
+/// The 'sync' node is not consumed. The recovery fails if no text was skipped.
+pub fn recover(node: impl ToNode, sync: impl ToNode) -> Node {
+    Recover(node.node().into(), sync.node().into())
+}
+
+/// Commits to the alternative taken so far such that a later failure does not try the remaining alternatives
+/// of the innermost 'Or' that encloses the cut. That 'Or' fails instead and enclosing choices backtrack as usual.
+pub fn cut() -> Node {
+    Cut
+}
+
+/// Succeeds if 'node' would succeed without consuming text or producing trees.
+pub fn peek(node: impl ToNode) -> Node {
+    Peek(node.node().into())
+}
+
+/// Succeeds if 'node' would fail without consuming text.
+pub fn not(node: impl ToNode) -> Node {
+    Not(node.node().into())
+}
+
 pub fn or(left: impl ToNode, right: impl ToNode) -> Node {
     Or(left.node().into(), right.node().into())
 }
@@ -913,12 +1358,16 @@ pub fn define(path: &str, node: impl ToNode) -> Node {
 
 pub fn syntax(path: &str, node: impl ToNode) -> Node {
     let depth = Path(".depth".into());
+    // The 'Store' push marks the start of the syntax and is consumed by the 'Spawn'.
     Define(
         Identifier::Path(path.into()),
-        and(
+        all(vec![
+            Store(0, Stack::Push),
             Set(depth.clone(), Set::Add(1)),
-            and(node, and(Set(depth, Set::Add(-1)), Spawn(path.into()))),
-        )
+            node.node(),
+            Set(depth, Set::Add(-1)),
+            Spawn(0, path.into()),
+        ])
         .into(),
     )
 }
@@ -939,6 +1388,8 @@ pub fn prefix(precedence: usize, node: impl ToNode) -> Node {
     )
 }
 
+/// A syntax that wraps a 'postfix' starts after its left operand, such that the operand is not one of its
+/// children. A left recursive syntax adopts its left operand instead.
 pub fn postfix(precedence: usize, bind: Bind, node: impl ToNode) -> Node {
     and(
         Precede(precedence, bind, Stack::Push),
@@ -951,10 +1402,92 @@ pub fn precede(prefix: impl ToNode, postfix: impl ToNode) -> Node {
 }
 
 pub fn range(low: char, high: char) -> Node {
-    any((low as u8..=high as u8)
-        .into_iter()
-        .map(|index| text(index as char))
-        .collect())
+    one_of([low..=high])
+}
+
+/// Parses a character that is in any of the 'ranges'.
+pub fn one_of(ranges: impl IntoIterator<Item = RangeInclusive<char>>) -> Node {
+    Class(class::merge(ranges), false)
+}
+
+/// Parses a character that is in none of the 'ranges'.
+pub fn none_of(ranges: impl IntoIterator<Item = RangeInclusive<char>>) -> Node {
+    Class(class::merge(ranges), true)
+}
+
+/// Changes the value of the 'slot'.
+pub fn set(slot: &str, value: Set) -> Node {
+    Set(Path(slot.into()), value)
+}
+
+/// Succeeds if the values of the 'left' and 'right' slots satisfy 'compare'.
+pub fn check(left: &str, compare: If, right: &str) -> Node {
+    If(Path(left.into()), compare, Path(right.into()))
+}
+
+/// Parses 'node' and then restores the 'slots' to the values they had before it.
+pub fn scope(slots: &[&str], node: impl ToNode) -> Node {
+    let pushes = slots.iter().map(|slot| Push(Path((*slot).into())));
+    let pops = slots.iter().rev().map(|slot| Pop(Path((*slot).into())));
+    all(pushes.chain([node.node()]).chain(pops).collect())
+}
+
+/// Calls 'predicate' with the text that remains to be parsed and the values of the 'slots'. It succeeds
+/// by consuming the returned number of bytes, which must end at a character boundary, and fails on 'None'.
+/// A generator gives the predicate short printable candidates and keeps the prefix that it consumes.
+pub fn native(
+    slots: &[&str],
+    predicate: impl Fn(&str, &[isize]) -> Option<usize> + Send + Sync + 'static,
+) -> Node {
+    let slots = slots.iter().map(|slot| Path((*slot).into())).collect();
+    Native(slots, Predicate(Arc::new(predicate)))
+}
+
+/// Parses an integer in 'radix' into the 'slot'. The integer has no sign and fails to parse when it
+/// overflows.
+pub fn integer(slot: &str, radix: u32) -> Node {
+    assert!((2..=36).contains(&radix), "Invalid radix '{}'.", radix);
+    Integer(Path(slot.into()), radix)
+}
+
+/// Parses 'node' as many times as the value of the 'slot', or not at all if it is not positive. The
+/// count is read once such that a nested repetition may set the same slot.
+pub fn repeat_value(slot: &str, node: impl ToNode) -> Node {
+    Times(Path(slot.into()), node.node().into())
+}
+
+/// Parses the indentation at the start of a line that is deeper than the current level and pushes it
+/// as the new level. Each 'indent' must be followed by a 'dedent' that ends the block.
+pub fn indent() -> Node {
+    let (column, level) = (Path(".column".into()), Path(".indent".into()));
+    all(vec![
+        indentation(),
+        If(level.clone(), If::Less, column.clone()),
+        Push(level.clone()),
+        Set(level, Set::Copy(column)),
+    ])
+}
+
+/// Pops the level pushed by the last 'indent' without consuming text.
+pub fn dedent() -> Node {
+    Pop(Path(".indent".into()))
+}
+
+/// Parses the indentation at the start of a line that is equal to the current level.
+pub fn same_indent() -> Node {
+    let (column, level) = (Path(".column".into()), Path(".indent".into()));
+    and(indentation(), If(column, If::Equal, level))
+}
+
+/// Parses the indentation at the start of a line into the '.column' value where a space or a tab
+/// counts as one column.
+fn indentation() -> Node {
+    let column = Path(".column".into());
+    let space = and(
+        any(vec![Symbol(' '), Symbol('\t')]),
+        Set(column.clone(), Set::Add(1)),
+    );
+    and(Set(column, Set::Value(0)), repeat(.., space))
 }
 
 #[macro_export]
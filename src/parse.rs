@@ -1,12 +1,19 @@
+use crate::class;
 use crate::node::*;
-use crate::node::{If, Set};
-use std::collections::HashMap;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Display;
-use std::fmt::Error;
 use std::fmt::Formatter;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::{Range, RangeInclusive};
 use std::rc::Rc;
+use std::sync::Arc;
 use Identifier::*;
 use Node::*;
 
@@ -14,14 +21,54 @@ use Node::*;
     TODO: try to remove 'String.clone()' especially in the spawn logic
     TODO: add state nodes
     TODO: operator precedence parser
-    TODO: retain ambiguities when both branches of an 'Or' succeeds?
 */
 
-#[derive(Clone, Default)]
+/// Trees are only serialized since their values borrow the source text, which escaped values cannot do
+/// when deserialized. An 'OwnedTree' is deserialized instead.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Tree<'a> {
     pub kind: String,
+    /// Byte range of the source text covered by this tree.
+    pub span: Range<usize>,
     pub values: Vec<&'a str>,
+    /// Byte ranges of the source text of each of the 'values'.
+    pub spans: Vec<Range<usize>>,
     pub children: Vec<Tree<'a>>,
+    /// Names of some of the 'values' as '(name, index)' pairs in order.
+    pub labels: Vec<(String, usize)>,
+    /// Names of some of the 'children' as '(name, index)' pairs in order.
+    pub fields: Vec<(String, usize)>,
+}
+
+/// A 'Tree' that owns its values such that it can outlive the source text.
+#[derive(Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OwnedTree {
+    pub kind: String,
+    pub span: Range<usize>,
+    pub values: Vec<String>,
+    pub spans: Vec<Range<usize>>,
+    pub children: Vec<OwnedTree>,
+    pub labels: Vec<(String, usize)>,
+    pub fields: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Expect {
+    Symbol(char),
+    Text(String),
+    Rule(String),
+    /// An inclusive range of characters.
+    Range(char, char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub index: usize,
+    pub line: usize,
+    pub column: usize,
+    pub expected: Vec<Expect>,
 }
 
 #[derive(Clone)]
@@ -29,74 +76,1251 @@ pub struct Parser {
     root: Parse,
     references: Vec<Parse>,
     indices: HashMap<Identifier, usize>,
+    depth: usize,
+    memoize: usize,
 }
 
-type Parse = Rc<dyn Fn(&mut State) -> bool>;
+pub(crate) type Parse = Arc<dyn Fn(&mut State) -> bool + Send + Sync>;
+
+/// A value built by an action, which is a 'T' of the 'Actions<T>' that the action belongs to.
+pub(crate) type Value = Rc<dyn Any>;
+pub(crate) type Reduce = dyn Fn(&Reduced, Vec<Value>) -> Option<Value>;
+pub(crate) type Reducers = HashMap<String, Box<Reduce>>;
+
+/// Typed reducers of syntaxes that build a 'T' for each syntax while parsing instead of a 'Tree'.
+/// A syntax whose kind has no reducer drops its stores and passes the values of its children to its parent.
+pub struct Actions<T> {
+    reducers: Reducers,
+    marker: PhantomData<fn() -> T>,
+}
 
+/// The stores of a syntax that 'Actions' reduce, with the names given to them and to its children.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reduced<'a> {
+    pub values: Vec<&'a str>,
+    /// Names of some of the 'values' as '(name, index)' pairs in order.
+    pub labels: Vec<(String, usize)>,
+    /// Names of some of the children as '(name, index)' pairs in order.
+    pub fields: Vec<(String, usize)>,
+}
+
+/// A name given by a 'Label' to the store, or by a 'Field' to the tree or to the result at a position of
+/// its stack.
 #[derive(Clone)]
-struct State<'a, 'b> {
+pub(crate) enum Label {
+    Value(String, usize),
+    Field(String, usize),
+    Result(String, usize),
+}
+
+/// Names with the positions of the values or children they apply to.
+type Names = Vec<(String, usize)>;
+
+impl Label {
+    /// Moves the position of the label from the lengths of the stacks at 'start' to zero, or back if
+    /// 'back' is set.
+    fn shift(&self, start: Lengths, back: bool) -> Self {
+        let shift = |position: usize, length: usize| {
+            if back {
+                position + length
+            } else {
+                position - length
+            }
+        };
+        match self {
+            Label::Value(name, position) => {
+                Label::Value(name.clone(), shift(*position, start.stores))
+            }
+            Label::Field(name, position) => {
+                Label::Field(name.clone(), shift(*position, start.trees))
+            }
+            Label::Result(name, position) => {
+                Label::Result(name.clone(), shift(*position, start.results))
+            }
+        }
+    }
+}
+
+/// Lengths of the stacks of a 'State'.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Lengths {
+    pub trees: usize,
+    pub stores: usize,
+    pub indices: usize,
+    pub precedences: usize,
+    pub marks: usize,
+    pub saves: usize,
+    pub captures: usize,
+    pub labels: usize,
+    pub results: usize,
+    pub diagnostics: usize,
+}
+
+/// The effect of a successful reference on a 'State'.
+#[derive(Clone)]
+pub(crate) struct Effect<'a> {
+    pub index: usize,
+    pub precedence: usize,
+    pub values: Vec<isize>,
+    pub trees: Vec<(Tree<'a>, isize)>,
+    pub stores: Vec<((&'a str, Range<usize>), isize)>,
+    pub indices: Vec<usize>,
+    pub precedences: Vec<usize>,
+    /// Marks whose numbers of children are relative to the length of the children when the effect started.
+    pub marks: Vec<usize>,
+    pub saves: Vec<(usize, isize)>,
+    pub captures: Vec<((String, &'a str), isize)>,
+    /// Labels whose positions are relative to the lengths of the stacks when the effect started.
+    pub labels: Vec<(Label, isize)>,
+    pub results: Vec<(Value, isize)>,
+    pub diagnostics: Vec<Error>,
+    /// Number of cuts that happened during the effect.
+    pub cuts: usize,
+}
+
+/// An operation that must be undone to backtrack. Pops record the length of the stack after the pop.
+pub(crate) enum Undo<'a> {
+    Value(usize, isize),
+    Index(usize, usize),
+    Precedence(usize, usize),
+    Mark(usize, usize),
+    Save(usize, (usize, isize)),
+    /// A 'Spawn' records the lengths of the stacks after it, the popped start index, the depths
+    /// of the consumed stores and trees such that they can be restored from the spawned tree and
+    /// the captures and labels that went out of scope.
+    Spawn {
+        lengths: Lengths,
+        index: usize,
+        stores: Vec<isize>,
+        trees: Vec<isize>,
+        captures: Vec<((String, &'a str), isize)>,
+        labels: Vec<(Label, isize)>,
+    },
+    /// A 'Spawn' that was reduced by an action records the lengths of the stacks before the reduced value
+    /// was pushed, the popped start index and the consumed stores, values, captures and labels.
+    Reduce {
+        lengths: Lengths,
+        index: usize,
+        stores: Vec<((&'a str, Range<usize>), isize)>,
+        results: Vec<(Value, isize)>,
+        captures: Vec<((String, &'a str), isize)>,
+        labels: Vec<(Label, isize)>,
+    },
+}
+
+/// A point to which a 'State' can backtrack.
+pub(crate) struct Checkpoint {
+    pub index: usize,
+    pub precedence: usize,
+    pub lengths: Lengths,
+    pub lows: Lengths,
+    pub trail: usize,
+}
+
+/// Decisions of the 'Or' nodes as '(alternative, alternatives)' pairs where 'cursor' is the next decision.
+#[derive(Default)]
+pub(crate) struct Choices {
+    pub decisions: Vec<(usize, usize)>,
+    pub cursor: usize,
+}
+
+/// Iterates over every distinct successful parse of a text. Each parse runs the parser with a different
+/// sequence of forced 'Or' decisions, such that alternatives are explored even when a previous one succeeded.
+pub struct Parses<'a, 'b> {
+    parser: &'b Parser,
+    text: &'a str,
+    choices: RefCell<Choices>,
+    parses: HashSet<Vec<Tree<'a>>>,
+    done: bool,
+}
+
+/// A memoized reference with its effect, where 'None' is a failure, and the furthest index with the
+/// expectations that it recorded such that errors are the same whether it is replayed or parsed.
+#[derive(Clone)]
+pub(crate) struct Entry<'a> {
+    pub effect: Option<Effect<'a>>,
+    pub failure: (usize, Vec<Expect>),
+}
+
+/// Packrat memoization table keyed by the text index and then by '(reference, precedence, hash of the
+/// values)' where 'count' is the number of entries. Seeds of left recursive references are keyed by
+/// '(reference, index)' and hold the best effect so far, the depth at which it was produced and whether
+/// left recursion was detected.
+pub(crate) struct Memo<'a> {
+    pub capacity: usize,
+    pub entries: BTreeMap<usize, HashMap<(usize, usize, u64), Entry<'a>>>,
+    pub count: usize,
+    pub seeds: HashMap<(usize, usize), (Option<Effect<'a>>, isize, bool)>,
+}
+
+pub(crate) struct State<'a, 'b> {
     pub index: usize,
     pub text: &'a str,
     pub references: &'b Vec<Parse>,
     pub trees: Vec<(Tree<'a>, isize)>,
     pub precedences: Vec<usize>,
+    /// Numbers of trees when the 'Field' pushes that are not popped yet happened.
+    pub marks: Vec<usize>,
     pub indices: Vec<usize>,
-    pub stores: Vec<(&'a str, isize)>,
+    pub stores: Vec<((&'a str, Range<usize>), isize)>,
     pub precedence: usize,
     pub values: Vec<isize>,
+    /// Values saved by 'Push' nodes as '(slot, value)' pairs that are restored by 'Pop' nodes.
+    pub saves: Vec<(usize, isize)>,
+    /// Texts of the named stores as '((name, text), depth)' pairs that go out of scope with their syntax.
+    pub captures: Vec<((String, &'a str), isize)>,
+    /// Names of the stores and trees that are adopted with them by their syntax.
+    pub labels: Vec<(Label, isize)>,
+    /// Values built by the 'actions' as '(value, depth)' pairs, which replace the 'trees' when there are actions.
+    pub results: Vec<(Value, isize)>,
+    pub actions: Option<&'b Reducers>,
+    pub depth: usize,
+    pub failure: &'b RefCell<(usize, Vec<Expect>)>,
+    pub memo: &'b RefCell<Memo<'a>>,
+    /// Lowest lengths that the stacks have reached since the enclosing memoized reference started.
+    pub lows: Lengths,
+    /// Errors that were recovered from.
+    pub diagnostics: Vec<Error>,
+    /// When present, 'Or' nodes follow these decisions instead of committing to their first success.
+    pub choices: Option<&'b RefCell<Choices>>,
+    /// Operations to undo when backtracking to a 'Checkpoint'.
+    pub trail: Vec<Undo<'a>>,
+    /// Number of live checkpoints. The trail is cleared when the last one is released.
+    pub checkpoints: usize,
+    /// Number of cuts so far. An 'Or' does not backtrack into its alternatives once it has changed.
+    pub cuts: usize,
+}
+
+impl Lengths {
+    pub fn of(state: &State) -> Self {
+        Lengths {
+            trees: state.trees.len(),
+            stores: state.stores.len(),
+            indices: state.indices.len(),
+            precedences: state.precedences.len(),
+            marks: state.marks.len(),
+            saves: state.saves.len(),
+            captures: state.captures.len(),
+            labels: state.labels.len(),
+            results: state.results.len(),
+            diagnostics: state.diagnostics.len(),
+        }
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        Lengths {
+            trees: self.trees.min(other.trees),
+            stores: self.stores.min(other.stores),
+            indices: self.indices.min(other.indices),
+            precedences: self.precedences.min(other.precedences),
+            marks: self.marks.min(other.marks),
+            saves: self.saves.min(other.saves),
+            captures: self.captures.min(other.captures),
+            labels: self.labels.min(other.labels),
+            results: self.results.min(other.results),
+            diagnostics: self.diagnostics.min(other.diagnostics),
+        }
+    }
+}
+
+impl<'a> State<'a, '_> {
+    /// Records that 'expect' would have allowed the parse to progress at 'index'.
+    /// Only the expectations at the furthest index are retained.
+    pub fn expect(&self, index: usize, expect: impl FnOnce() -> Expect) {
+        let mut failure = self.failure.borrow_mut();
+        if index > failure.0 {
+            failure.0 = index;
+            failure.1.clear();
+        }
+        if index == failure.0 {
+            let expect = expect();
+            if !failure.1.contains(&expect) {
+                failure.1.push(expect);
+            }
+        }
+    }
+
+    /// Records the expectations of a 'failure' as if they had been expected again.
+    pub fn fail(&self, failure: (usize, Vec<Expect>)) {
+        let (index, expected) = failure;
+        for expect in expected {
+            self.expect(index, || expect);
+        }
+    }
+
+    /// Must be called after popping from any of the stacks.
+    pub fn lower(&mut self) {
+        self.lows = self.lows.min(Lengths::of(self));
+    }
+
+    /// Records a point to backtrack to, which must be released once it will not be restored anymore.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.checkpoints += 1;
+        Checkpoint {
+            index: self.index,
+            precedence: self.precedence,
+            lengths: Lengths::of(self),
+            lows: self.lows,
+            trail: self.trail.len(),
+        }
+    }
+
+    /// Undoes the operations since the 'checkpoint' in reverse order and truncates the stacks to
+    /// remove what was pushed since then.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        debug_assert!(self.trail.len() >= checkpoint.trail);
+        while self.trail.len() > checkpoint.trail {
+            match self.trail.pop() {
+                Some(Undo::Value(index, value)) => self.values[index] = value,
+                Some(Undo::Index(length, index)) => {
+                    self.indices.truncate(length);
+                    self.indices.push(index);
+                }
+                Some(Undo::Precedence(length, precedence)) => {
+                    self.precedences.truncate(length);
+                    self.precedences.push(precedence);
+                }
+                Some(Undo::Mark(length, mark)) => {
+                    self.marks.truncate(length);
+                    self.marks.push(mark);
+                }
+                Some(Undo::Save(length, save)) => {
+                    self.saves.truncate(length);
+                    self.saves.push(save);
+                }
+                Some(Undo::Spawn {
+                    lengths,
+                    index,
+                    stores,
+                    trees,
+                    captures,
+                    labels,
+                }) => {
+                    self.captures.truncate(lengths.captures);
+                    self.captures.extend(captures);
+                    self.labels.truncate(lengths.labels);
+                    self.labels.extend(labels);
+                    self.trees.truncate(lengths.trees);
+                    if let Some((tree, _)) = self.trees.pop() {
+                        let values = tree.values.into_iter().zip(tree.spans);
+                        self.stores.truncate(lengths.stores);
+                        self.stores.extend(values.zip(stores));
+                        self.trees.extend(tree.children.into_iter().zip(trees));
+                    }
+                    self.indices.truncate(lengths.indices);
+                    self.indices.push(index);
+                }
+                Some(Undo::Reduce {
+                    lengths,
+                    index,
+                    stores,
+                    results,
+                    captures,
+                    labels,
+                }) => {
+                    self.captures.truncate(lengths.captures);
+                    self.captures.extend(captures);
+                    self.labels.truncate(lengths.labels);
+                    self.labels.extend(labels);
+                    self.results.truncate(lengths.results);
+                    self.results.extend(results);
+                    self.stores.truncate(lengths.stores);
+                    self.stores.extend(stores);
+                    self.indices.truncate(lengths.indices);
+                    self.indices.push(index);
+                }
+                None => break,
+            }
+        }
+        self.trees.truncate(checkpoint.lengths.trees);
+        self.stores.truncate(checkpoint.lengths.stores);
+        self.indices.truncate(checkpoint.lengths.indices);
+        self.precedences.truncate(checkpoint.lengths.precedences);
+        self.marks.truncate(checkpoint.lengths.marks);
+        self.saves.truncate(checkpoint.lengths.saves);
+        self.captures.truncate(checkpoint.lengths.captures);
+        self.labels.truncate(checkpoint.lengths.labels);
+        self.results.truncate(checkpoint.lengths.results);
+        self.diagnostics.truncate(checkpoint.lengths.diagnostics);
+        self.index = checkpoint.index;
+        self.precedence = checkpoint.precedence;
+        self.lows = checkpoint.lows;
+    }
+
+    /// Releases a checkpoint that will not be restored anymore. Once no checkpoint is live, nothing can
+    /// be undone such that the trail is cleared.
+    pub fn release(&mut self, _: Checkpoint) {
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.trail.clear();
+        }
+    }
+
+    pub fn set(&mut self, index: usize, value: isize) {
+        let value = mem::replace(&mut self.values[index], value);
+        self.trail.push(Undo::Value(index, value));
+    }
+
+    /// Saves the value of the slot at 'index'.
+    pub fn push(&mut self, index: usize) {
+        self.saves.push((index, self.values[index]));
+    }
+
+    /// Restores the value of the slot at 'index' if it is the last one that was saved.
+    pub fn pop(&mut self, index: usize) -> bool {
+        match self.saves.last() {
+            Some(&(slot, value)) if slot == index => {
+                self.saves.pop();
+                self.lower();
+                self.trail.push(Undo::Save(self.saves.len(), (slot, value)));
+                self.set(index, value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn symbol(&mut self, symbol: char) -> bool {
+        match self.text.get(self.index..) {
+            Some(slice) if slice.starts_with(symbol) => {
+                self.index += symbol.len_utf8();
+                true
+            }
+            _ => {
+                self.expect(self.index, || Expect::Symbol(symbol));
+                false
+            }
+        }
+    }
+
+    pub fn text(&mut self, text: &str) -> bool {
+        match self.text.get(self.index..) {
+            Some(slice) if slice.starts_with(text) => {
+                self.index += text.len();
+                true
+            }
+            _ => {
+                self.expect(self.index, || Expect::Text(text.into()));
+                false
+            }
+        }
+    }
+
+    /// Records that a character in any of the 'ranges' was expected at the current index.
+    pub fn expect_ranges(&self, ranges: &[RangeInclusive<char>]) {
+        for range in ranges {
+            self.expect(self.index, || match (*range.start(), *range.end()) {
+                (low, high) if low == high => Expect::Symbol(low),
+                (low, high) => Expect::Range(low, high),
+            });
+        }
+    }
+
+    /// Parses a character that is in the sorted and disjoint 'ranges' or not in them if 'negate' is set.
+    pub fn class(&mut self, ranges: &[RangeInclusive<char>], negate: bool) -> bool {
+        match self.text[self.index..].chars().next() {
+            Some(symbol) if class::search(ranges, symbol).is_some() != negate => {
+                self.index += symbol.len_utf8();
+                true
+            }
+            _ => {
+                if negate {
+                    self.expect_ranges(&class::complement(ranges));
+                } else {
+                    self.expect_ranges(ranges);
+                }
+                false
+            }
+        }
+    }
+
+    /// Moves past the next symbol of the text.
+    pub fn skip(&mut self) {
+        self.index += self.text[self.index..]
+            .chars()
+            .next()
+            .map_or(1, char::len_utf8);
+    }
+
+    /// Pops the start index of a tree of 'kind' and adopts the stores and trees pushed deeper than it.
+    pub fn spawn(&mut self, kind: &str, shift: usize) -> bool {
+        match self.indices.pop() {
+            Some(index) if self.actions.is_some() => self.reduce(kind, index),
+            Some(index) => {
+                let depth = self.values[self.depth];
+                let (stores, store_depths): (Vec<_>, _) =
+                    consume(&mut self.stores, depth).into_iter().unzip();
+                let (children, tree_depths) = consume(&mut self.trees, depth).into_iter().unzip();
+                let (values, spans) = stores.into_iter().unzip();
+                let captures = consume(&mut self.captures, depth);
+                let labels = consume(&mut self.labels, depth);
+                let (names, fields) = self.names(&labels);
+                let tree = Tree {
+                    kind: kind.into(),
+                    span: index..self.index - shift,
+                    values,
+                    spans,
+                    children,
+                    labels: names,
+                    fields,
+                };
+                self.lower();
+                self.trees.push((tree, depth));
+                self.trail.push(Undo::Spawn {
+                    lengths: Lengths::of(self),
+                    index,
+                    stores: store_depths,
+                    trees: tree_depths,
+                    captures,
+                    labels,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Splits the 'labels' of a syntax whose stores and children were consumed into the names of its values
+    /// and of its children, with positions relative to what was consumed.
+    fn names(&self, labels: &[(Label, isize)]) -> (Names, Names) {
+        let (mut names, mut fields) = (Vec::new(), Vec::new());
+        for (label, _) in labels.iter() {
+            match label {
+                Label::Value(name, position) => {
+                    names.push((name.clone(), position - self.stores.len()))
+                }
+                Label::Field(name, position) => {
+                    fields.push((name.clone(), position - self.trees.len()))
+                }
+                Label::Result(name, position) => {
+                    fields.push((name.clone(), position - self.results.len()))
+                }
+            }
+        }
+        (names, fields)
+    }
+
+    /// Reduces the stores and values pushed deeper than the syntax of 'kind' that started at 'index' with
+    /// the action of 'kind'. The syntax fails and leaves the stacks unchanged if the action fails.
+    fn reduce(&mut self, kind: &str, index: usize) -> bool {
+        let depth = self.values[self.depth];
+        let stores = consume(&mut self.stores, depth);
+        let results = consume(&mut self.results, depth);
+        let captures = consume(&mut self.captures, depth);
+        let labels = consume(&mut self.labels, depth);
+        self.lower();
+        let lengths = Lengths::of(self);
+        match self.actions.and_then(|actions| actions.get(kind)) {
+            Some(reduce) => {
+                let (names, fields) = self.names(&labels);
+                let reduced = Reduced {
+                    values: stores.iter().map(|store| store.0 .0).collect(),
+                    labels: names,
+                    fields,
+                };
+                let children = results.iter().map(|result| result.0.clone()).collect();
+                match reduce(&reduced, children) {
+                    Some(value) => self.results.push((value, depth)),
+                    None => {
+                        self.stores.extend(stores);
+                        self.results.extend(results);
+                        self.captures.extend(captures);
+                        self.labels.extend(labels);
+                        self.indices.push(index);
+                        return false;
+                    }
+                }
+            }
+            None => {
+                let values = results.iter().map(|result| (result.0.clone(), depth));
+                self.results.extend(values);
+            }
+        }
+        self.trail.push(Undo::Reduce {
+            lengths,
+            index,
+            stores,
+            results,
+            captures,
+            labels,
+        });
+        true
+    }
+
+    pub fn store(&mut self, shift: usize, stack: Stack) -> bool {
+        match stack {
+            Stack::Push => {
+                self.indices.push(self.index - shift);
+                true
+            }
+            Stack::Pop => match self.indices.pop() {
+                Some(index) => {
+                    self.lower();
+                    self.trail.push(Undo::Index(self.indices.len(), index));
+                    let depth = self.values[self.depth];
+                    let span = index..self.index - shift;
+                    let value = &self.text[span.clone()];
+                    self.stores.push(((value, span), depth));
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Parses the digits of an integer in 'radix' into the value at 'index'. Fails if the integer overflows.
+    pub fn integer(&mut self, index: usize, radix: u32) -> bool {
+        let mut value: Option<isize> = Some(0);
+        let mut length = 0;
+        for symbol in self.text[self.index..].chars() {
+            match symbol.to_digit(radix) {
+                Some(digit) => {
+                    value = value
+                        .and_then(|value| value.checked_mul(radix as isize))
+                        .and_then(|value| value.checked_add(digit as isize));
+                    length += symbol.len_utf8();
+                }
+                None => break,
+            }
+        }
+        if length == 0 {
+            self.expect_ranges(&class::digits(radix));
+            return false;
+        }
+        match value {
+            Some(value) => {
+                self.index += length;
+                self.set(index, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Calls 'predicate' with the values at 'indices' and consumes the length that it returns.
+    pub fn native(&mut self, indices: &[usize], predicate: &Predicate) -> bool {
+        let values: Vec<_> = indices.iter().map(|&index| self.values[index]).collect();
+        let length = (predicate.0)(&self.text[self.index..], &values);
+        match length.and_then(|length| self.index.checked_add(length)) {
+            Some(index) if self.text.is_char_boundary(index) => {
+                self.index = index;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Names the text of the last store such that 'back' can match it again.
+    pub fn name(&mut self, name: &str) -> bool {
+        match self.stores.last() {
+            Some(&((text, _), _)) => {
+                let depth = self.values[self.depth];
+                self.captures.push(((name.into(), text), depth));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names the last store such that the syntax that adopts it can find it by name.
+    pub fn label(&mut self, name: &str) -> bool {
+        match self.stores.last() {
+            Some(&(_, depth)) => {
+                let position = self.stores.len() - 1;
+                // Naming below the stacks of a memoized reference prevents recording its effect.
+                self.lows.stores = self.lows.stores.min(position);
+                self.labels
+                    .push((Label::Value(name.into(), position), depth));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Marks the number of children, or pops the mark and names the last child such that the syntax that
+    /// adopts it can find it by name. Nothing is named if no child was pushed since the mark. The children
+    /// are results instead of trees when there are actions.
+    pub fn field(&mut self, name: &str, stack: Stack) -> bool {
+        let mark = match stack {
+            Stack::Push => {
+                self.marks.push(self.children(Lengths::of(self)));
+                return true;
+            }
+            Stack::Pop => match self.marks.pop() {
+                Some(mark) => mark,
+                None => return false,
+            },
+        };
+        self.lower();
+        let length = self.marks.len();
+        self.trail.push(Undo::Mark(length, mark));
+        let children = self.children(Lengths::of(self));
+        if mark >= children {
+            return true;
+        }
+        let position = children - 1;
+        // Naming below the stacks of a memoized reference prevents recording its effect.
+        let label = match self.actions {
+            Some(_) => {
+                self.lows.results = self.lows.results.min(position);
+                (
+                    Label::Result(name.into(), position),
+                    self.results[position].1,
+                )
+            }
+            None => {
+                self.lows.trees = self.lows.trees.min(position);
+                (Label::Field(name.into(), position), self.trees[position].1)
+            }
+        };
+        self.labels.push(label);
+        true
+    }
+
+    /// Length of the stack of children in 'lengths', which are results instead of trees when there are
+    /// actions.
+    fn children(&self, lengths: Lengths) -> usize {
+        match self.actions {
+            Some(_) => lengths.results,
+            None => lengths.trees,
+        }
+    }
+
+    /// Parses the text of the last capture named 'name' that is in scope.
+    pub fn back(&mut self, name: &str) -> bool {
+        let depth = self.values[self.depth];
+        let found = self
+            .captures
+            .iter()
+            .rposition(|capture| capture.0 .0 == name && capture.1 <= depth);
+        match found {
+            Some(position) => {
+                // Reading below the stacks of a memoized reference prevents recording its effect.
+                self.lows.captures = self.lows.captures.min(position);
+                let text = self.captures[position].0 .1;
+                self.text(text)
+            }
+            None => false,
+        }
+    }
+
+    pub fn precede(&mut self, precedence: usize, bind: Bind, stack: Stack) -> bool {
+        match (stack, bind) {
+            (Stack::Push, Bind::Left) if precedence <= self.precedence => false,
+            (Stack::Push, Bind::Right) if precedence < self.precedence => false,
+            (Stack::Push, _) => {
+                let precedence = mem::replace(&mut self.precedence, precedence);
+                self.precedences.push(precedence);
+                true
+            }
+            (Stack::Pop, _) => match self.precedences.pop() {
+                Some(precedence) => {
+                    self.lower();
+                    let length = self.precedences.len();
+                    self.trail.push(Undo::Precedence(length, precedence));
+                    self.precedence = precedence;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Records the error at 'index' that was recovered from by skipping the text since 'start'.
+    pub fn recovered(&mut self, start: usize, index: usize, mut expected: Vec<Expect>) {
+        expected.sort();
+        let error = Error::new(self.text, index, expected);
+        let depth = self.values[self.depth];
+        let span = start..self.index;
+        let tree = Tree {
+            kind: "error".into(),
+            span: span.clone(),
+            values: vec![&self.text[span.clone()]],
+            spans: vec![span],
+            children: Vec::new(),
+            labels: Vec::new(),
+            fields: Vec::new(),
+        };
+        self.trees.push((tree, depth));
+        self.diagnostics.push(error);
+    }
+
+    /// Produces the trees and diagnostics of a parse that ended with 'success'.
+    pub fn conclude(mut self, success: bool) -> (Vec<Tree<'a>>, Vec<Error>) {
+        if success && self.index == self.text.len() {
+            let trees = self.trees.drain(..).map(|pair| pair.0).collect();
+            return (trees, self.diagnostics);
+        }
+
+        let (index, mut expected) = self.failure.take();
+        expected.sort();
+        let (index, expected) = if index < self.index {
+            // The root succeeded without consuming the whole text and nothing was expected further.
+            (self.index, Vec::new())
+        } else {
+            (index, expected)
+        };
+        let error = Error::new(self.text, index, expected);
+        if success {
+            let trees = self.trees.drain(..).map(|pair| pair.0).collect();
+            let mut diagnostics = self.diagnostics;
+            diagnostics.push(error);
+            (trees, diagnostics)
+        } else {
+            (Vec::new(), vec![error])
+        }
+    }
+
+    /// Commits to the alternative taken so far. Memoized entries before the current index are dropped
+    /// such that the table only holds entries that follow the last cut.
+    pub fn cut(&mut self) {
+        self.cuts += 1;
+        let mut memo = self.memo.borrow_mut();
+        let entries = memo.entries.split_off(&self.index);
+        let dropped = mem::replace(&mut memo.entries, entries);
+        memo.count -= dropped.values().map(HashMap::len).sum::<usize>();
+    }
+
+    /// Captures the effect of what has been parsed since the stacks had the 'start' lengths and the
+    /// cuts had the 'cuts' count.
+    pub fn capture(&self, start: Lengths, cuts: usize) -> Effect<'a> {
+        Effect {
+            index: self.index,
+            precedence: self.precedence,
+            values: self.values.clone(),
+            trees: self.trees[start.trees..].to_vec(),
+            stores: self.stores[start.stores..].to_vec(),
+            indices: self.indices[start.indices..].to_vec(),
+            precedences: self.precedences[start.precedences..].to_vec(),
+            marks: self.marks[start.marks..]
+                .iter()
+                .map(|mark| mark - self.children(start))
+                .collect(),
+            saves: self.saves[start.saves..].to_vec(),
+            captures: self.captures[start.captures..].to_vec(),
+            labels: self.labels[start.labels..]
+                .iter()
+                .map(|(label, depth)| (label.shift(start, false), *depth))
+                .collect(),
+            results: self.results[start.results..].to_vec(),
+            diagnostics: self.diagnostics[start.diagnostics..].to_vec(),
+            cuts: self.cuts - cuts,
+        }
+    }
+
+    /// Applies a captured effect where the depth of its trees and stores is shifted by 'shift'.
+    pub fn replay(&mut self, effect: Effect<'a>, shift: isize) {
+        let start = Lengths::of(self);
+        self.index = effect.index;
+        self.precedence = effect.precedence;
+        for (index, value) in effect.values.into_iter().enumerate() {
+            let value = if index == self.depth {
+                value + shift
+            } else {
+                value
+            };
+            if self.values[index] != value {
+                self.set(index, value);
+            }
+        }
+        self.trees.extend(
+            effect
+                .trees
+                .into_iter()
+                .map(|(tree, depth)| (tree, depth + shift)),
+        );
+        self.stores.extend(
+            effect
+                .stores
+                .into_iter()
+                .map(|(store, depth)| (store, depth + shift)),
+        );
+        self.indices.extend(effect.indices);
+        self.precedences.extend(effect.precedences);
+        let children = self.children(start);
+        self.marks
+            .extend(effect.marks.into_iter().map(|mark| mark + children));
+        self.saves.extend(effect.saves);
+        self.captures.extend(
+            effect
+                .captures
+                .into_iter()
+                .map(|(capture, depth)| (capture, depth + shift)),
+        );
+        self.labels.extend(
+            effect
+                .labels
+                .into_iter()
+                .map(|(label, depth)| (label.shift(start, true), depth + shift)),
+        );
+        self.results.extend(
+            effect
+                .results
+                .into_iter()
+                .map(|(value, depth)| (value, depth + shift)),
+        );
+        self.diagnostics.extend(effect.diagnostics);
+        self.cuts += effect.cuts;
+    }
+
+    /// Parses the reference at 'index' with 'parser' while reusing or recording its effect in the memo table.
+    /// An effect is only recorded when the reference did not pop values below the stacks it started with,
+    /// such that it can be replayed regardless of what precedes it.
+    pub fn refer(&mut self, index: usize, parser: &Parse) -> bool {
+        if self.memo.borrow().capacity == 0 || self.choices.is_some() {
+            return parser(self);
+        }
+
+        let mut hasher = DefaultHasher::new();
+        self.values.hash(&mut hasher);
+        let (position, key) = (self.index, (index, self.precedence, hasher.finish()));
+        let entry = self
+            .memo
+            .borrow()
+            .entries
+            .get(&position)
+            .and_then(|entries| entries.get(&key).cloned());
+        match entry {
+            Some(Entry { effect, failure }) => {
+                self.fail(failure);
+                match effect {
+                    Some(effect) => {
+                        self.replay(effect, 0);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            None => {
+                let start = Lengths::of(self);
+                let cuts = self.cuts;
+                let lows = mem::replace(&mut self.lows, start);
+                // Parse with its own failure such that its expectations can be recorded with its entry.
+                let outer = self.failure.replace((self.index, Vec::new()));
+                let success = parser(self);
+                let failure = self.failure.replace(outer);
+                self.fail(failure.clone());
+                let inner = mem::replace(&mut self.lows, lows);
+                self.lower();
+                self.lows = self.lows.min(inner);
+
+                let mut memo = self.memo.borrow_mut();
+                if inner == start && memo.count < memo.capacity {
+                    let effect = if success {
+                        Some(self.capture(start, cuts))
+                    } else {
+                        None
+                    };
+                    let entry = Entry { effect, failure };
+                    if memo
+                        .entries
+                        .entry(position)
+                        .or_default()
+                        .insert(key, entry)
+                        .is_none()
+                    {
+                        memo.count += 1;
+                    }
+                }
+                success
+            }
+        }
+    }
+
+    /// Parses the left recursive reference at 'index' by growing a seed: a recursive invocation at the
+    /// same text index replays the best effect so far (initially a failure) and the reference is parsed
+    /// again as long as it consumes more text, which produces left associative trees.
+    pub fn grow(&mut self, index: usize, parser: &Parse) -> bool {
+        let key = (index, self.index);
+        let seed = self.memo.borrow_mut().seeds.get_mut(&key).map(|seed| {
+            seed.2 = true;
+            (seed.0.clone(), seed.1)
+        });
+        if let Some((effect, depth)) = seed {
+            return match effect {
+                Some(effect) => {
+                    let shift = self.values[self.depth] - depth;
+                    self.replay(effect, shift);
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let depth = self.values[self.depth];
+        self.memo
+            .borrow_mut()
+            .seeds
+            .insert(key, (None, depth, false));
+        let start = Lengths::of(self);
+        let cuts = self.cuts;
+        let lows = mem::replace(&mut self.lows, start);
+        let origin = self.checkpoint();
+        let success = parser(self);
+        while success && self.lows == start && self.memo.borrow().seeds[&key].2 {
+            let effect = self.capture(start, cuts);
+            if let Some(seed) = self.memo.borrow_mut().seeds.get_mut(&key) {
+                seed.0 = Some(effect.clone());
+            }
+            let index = self.index;
+            self.restore(&origin);
+            if !parser(self) || self.index <= index {
+                // Backtrack to the best effect.
+                self.restore(&origin);
+                self.replay(effect, 0);
+                break;
+            }
+        }
+        self.release(origin);
+        self.memo.borrow_mut().seeds.remove(&key);
+        self.lows = lows.min(self.lows);
+        success
+    }
+}
+
+/// Pops the pairs deeper than 'depth' in order. Callers must 'lower' the state after consuming.
+fn consume<T>(pairs: &mut Vec<(T, isize)>, depth: isize) -> Vec<(T, isize)> {
+    let mut values = Vec::new();
+    while let Some(pair) = pairs.pop() {
+        if pair.1 > depth {
+            values.push(pair);
+        } else {
+            pairs.push(pair);
+            break;
+        }
+    }
+    values.reverse();
+    values
+}
+
+/// Takes the 'T' out of a 'value' of an 'Actions<T>'. It is cloned if the trail or the memo still shares it.
+fn take<T: Clone + 'static>(value: Value) -> T {
+    match value.downcast::<T>() {
+        Ok(value) => Rc::try_unwrap(value).unwrap_or_else(|value| (*value).clone()),
+        Err(_) => panic!("Invalid value type."),
+    }
+}
+
+impl<T: Clone + 'static> Actions<T> {
+    pub fn new() -> Self {
+        Actions {
+            reducers: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Reduces the syntaxes of 'kind' with 'reduce', which receives their stores and the values of their
+    /// children in order. If 'reduce' returns 'None', the syntax fails such that the parser backtracks.
+    pub fn on(
+        mut self,
+        kind: &str,
+        reduce: impl Fn(&Reduced, Vec<T>) -> Option<T> + 'static,
+    ) -> Self {
+        let reduce = move |reduced: &Reduced, children: Vec<Value>| -> Option<Value> {
+            let children = children.into_iter().map(take).collect();
+            Some(Rc::new(reduce(reduced, children)?))
+        };
+        self.reducers.insert(kind.into(), Box::new(reduce));
+        self
+    }
+}
+
+impl<T: Clone + 'static> Default for Actions<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts a byte 'index' into a 1-based (line, column) pair where columns are counted in characters.
+pub fn position(text: &str, index: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut column = 1;
+    for (current, symbol) in text.char_indices() {
+        if current >= index {
+            break;
+        } else if symbol == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    (line, column)
 }
 
 impl Parser {
+    /// Enables packrat memoization of references with a table of at most 'capacity' entries per parse.
+    /// A 'capacity' of 0 disables memoization.
+    pub fn memoize(mut self, capacity: usize) -> Self {
+        self.memoize = capacity;
+        self
+    }
+
     pub fn parse<'a>(&self, text: &'a str) -> Vec<Tree<'a>> {
+        self.try_parse(text).unwrap_or_default()
+    }
+
+    pub fn try_parse<'a>(&self, text: &'a str) -> Result<Vec<Tree<'a>>, Error> {
+        let (trees, mut diagnostics) = self.recover(text);
+        if diagnostics.is_empty() {
+            Ok(trees)
+        } else {
+            Err(diagnostics.remove(0))
+        }
+    }
+
+    /// Parses 'text' while recovering from errors with 'recover' nodes. The errors that were recovered from
+    /// are returned with the trees where each recovery produced an 'error' tree that covers the skipped text.
+    /// If text remains after the root, a final error is appended to the diagnostics with the partial trees.
+    /// If the root fails, only the final error is returned.
+    pub fn recover<'a>(&self, text: &'a str) -> (Vec<Tree<'a>>, Vec<Error>) {
+        self.run(text, None, None, |state, success| state.conclude(success))
+    }
+
+    /// Parses 'text' while building the values of the syntaxes with 'actions' instead of trees, and returns
+    /// the values of the root syntaxes. Recovered errors are returned as errors, like with 'try_parse'.
+    pub fn reduce<T: Clone + 'static>(
+        &self,
+        text: &str,
+        actions: &Actions<T>,
+    ) -> Result<Vec<T>, Error> {
+        self.run(text, None, Some(&actions.reducers), |mut state, success| {
+            let results = mem::take(&mut state.results);
+            let (_, mut diagnostics) = state.conclude(success);
+            if diagnostics.is_empty() {
+                Ok(results.into_iter().map(|result| take(result.0)).collect())
+            } else {
+                Err(diagnostics.remove(0))
+            }
+        })
+    }
+
+    /// Iterates over all the successful parses of 'text' where every successful alternative of an 'Or' is
+    /// retained. Recovery, memoization and cuts are disabled and left recursive references are grown greedily.
+    /// The text is parsed again for every sequence of decisions, such that the number of runs grows
+    /// exponentially with the number of ambiguous 'Or' nodes that are reached.
+    pub fn parse_all<'a, 'b>(&'b self, text: &'a str) -> Parses<'a, 'b> {
+        Parses {
+            parser: self,
+            text,
+            choices: RefCell::new(Choices::default()),
+            parses: HashSet::new(),
+            done: false,
+        }
+    }
+
+    /// Runs the root on 'text' and passes the final state with whether the root succeeded to 'conclude'.
+    fn run<'a, R>(
+        &self,
+        text: &'a str,
+        choices: Option<&RefCell<Choices>>,
+        actions: Option<&Reducers>,
+        conclude: impl FnOnce(State<'a, '_>, bool) -> R,
+    ) -> R {
+        let failure = RefCell::new((0, Vec::new()));
+        let memo = RefCell::new(Memo {
+            capacity: self.memoize,
+            entries: BTreeMap::new(),
+            count: 0,
+            seeds: HashMap::new(),
+        });
         let mut state = State {
             index: 0,
             text,
             references: &self.references,
             trees: Vec::new(),
             precedences: Vec::new(),
+            marks: Vec::new(),
             indices: Vec::new(),
             stores: Vec::new(),
             precedence: 0,
             values: vec![0; self.indices.len()],
+            saves: Vec::new(),
+            captures: Vec::new(),
+            labels: Vec::new(),
+            results: Vec::new(),
+            actions,
+            depth: self.depth,
+            failure: &failure,
+            memo: &memo,
+            lows: Lengths::default(),
+            diagnostics: Vec::new(),
+            choices,
+            trail: Vec::new(),
+            checkpoints: 0,
+            cuts: 0,
         };
 
-        if (self.root)(&mut state) && state.index == state.text.len() {
-            state.trees.drain(..).map(|pair| pair.0).collect()
-        } else {
-            Vec::new()
+        let success = (self.root)(&mut state);
+        conclude(state, success)
+    }
+}
+
+impl Choices {
+    /// Returns the next decision of an 'Or' with 'count' alternatives, which is the first alternative
+    /// when there is no forced decision.
+    pub fn next(&mut self, count: usize) -> usize {
+        let cursor = self.cursor;
+        self.cursor += 1;
+        match self.decisions.get(cursor) {
+            Some(decision) => decision.0,
+            None => {
+                self.decisions.push((0, count));
+                0
+            }
+        }
+    }
+
+    /// Moves to the next unexplored sequence of decisions, if any.
+    pub fn advance(&mut self) -> bool {
+        self.decisions.truncate(self.cursor);
+        self.cursor = 0;
+        while let Some(decision) = self.decisions.last_mut() {
+            if decision.0 + 1 < decision.1 {
+                decision.0 += 1;
+                return true;
+            }
+            self.decisions.pop();
+        }
+        false
+    }
+}
+
+impl<'a> Iterator for Parses<'a, '_> {
+    type Item = Vec<Tree<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (trees, diagnostics) =
+                self.parser
+                    .run(self.text, Some(&self.choices), None, |state, success| {
+                        state.conclude(success)
+                    });
+            self.done = !self.choices.borrow_mut().advance();
+            if diagnostics.is_empty() && self.parses.insert(trees.clone()) {
+                return Some(trees);
+            }
         }
+        None
     }
 }
 
 impl From<Node> for Parser {
     fn from(node: Node) -> Parser {
         struct State {
-            depth: usize,
             references: Vec<Option<Parse>>,
-        }
-
-        fn consume<T>(pairs: &mut Vec<(T, isize)>, depth: isize) -> Vec<T> {
-            let mut values = Vec::new();
-            while let Some(pair) = pairs.pop() {
-                if pair.1 > depth {
-                    values.push(pair.0);
-                } else {
-                    pairs.push(pair);
-                    break;
-                }
-            }
-            values.reverse();
-            values
+            rules: HashMap<usize, String>,
+            recursive: HashSet<usize>,
         }
 
         fn next(node: &Node, state: &State) -> Parse {
             match node {
-                True => Rc::new(|_| true),
-                False => Rc::new(|_| false),
+                True => Arc::new(|_| true),
+                False => Arc::new(|_| false),
                 And(_, _) => {
                     let nodes = node.flatten();
                     let parsers: Vec<_> = nodes.iter().map(|node| next(node, state)).collect();
-                    Rc::new(move |state| {
+                    Arc::new(move |state| {
                         for parser in &parsers {
                             if parser(state) == false {
                                 return false;
@@ -108,128 +1332,238 @@ impl From<Node> for Parser {
                 Or(_, _) => {
                     let nodes = node.flatten();
                     let parsers: Vec<_> = nodes.iter().map(|node| next(node, state)).collect();
-                    Rc::new(move |state| {
+                    Arc::new(move |state| {
+                        if let Some(choices) = state.choices {
+                            let choice = choices.borrow_mut().next(parsers.len());
+                            return parsers[choice](state);
+                        }
+
+                        let checkpoint = state.checkpoint();
+                        let cuts = state.cuts;
+                        let mut success = false;
                         for parser in &parsers {
-                            let mut local = state.clone();
-                            if parser(&mut local) {
-                                *state = local;
-                                return true;
+                            if parser(state) {
+                                success = true;
+                                break;
+                            } else if state.cuts > cuts {
+                                break;
                             }
+                            state.restore(&checkpoint);
                         }
-                        false
+                        state.release(checkpoint);
+                        // A cut only commits the innermost 'Or' that encloses it.
+                        state.cuts = cuts;
+                        success
                     })
                 }
-                &Refer(Index(index)) => match &state.references[index] {
-                    Some(parser) => parser.clone(),
-                    None => Rc::new(move |state| state.references[index].clone()(state)),
-                },
-                Spawn(kind) => {
-                    let depth = state.depth;
-                    let kind = kind.clone();
-                    Rc::new(move |state| {
-                        let depth = state.values[depth];
-                        let tree = Tree {
-                            kind: kind.clone(),
-                            values: consume(&mut state.stores, depth),
-                            children: consume(&mut state.trees, depth),
+                &Refer(Index(index)) => {
+                    let parser: Parse =
+                        match (&state.references[index], state.recursive.contains(&index)) {
+                            (Some(parser), false) => {
+                                let parser = parser.clone();
+                                Arc::new(move |state| state.refer(index, &parser))
+                            }
+                            (Some(parser), true) => {
+                                let parser = parser.clone();
+                                Arc::new(move |state| state.grow(index, &parser))
+                            }
+                            (None, false) => Arc::new(move |state| {
+                                let parser = state.references[index].clone();
+                                state.refer(index, &parser)
+                            }),
+                            (None, true) => Arc::new(move |state| {
+                                let parser = state.references[index].clone();
+                                state.grow(index, &parser)
+                            }),
                         };
-                        state.trees.push((tree, depth));
-                        true
-                    })
-                }
-                &Symbol(symbol) => Rc::new(move |state| match state.text.get(state.index..) {
-                    Some(slice) if slice.starts_with(symbol) => {
-                        state.index += symbol.len_utf8();
-                        true
+                    match state.rules.get(&index) {
+                        Some(rule) => {
+                            let rule = rule.clone();
+                            Arc::new(move |state| {
+                                let index = state.index;
+                                if parser(state) {
+                                    true
+                                } else {
+                                    state.expect(index, || Expect::Rule(rule.clone()));
+                                    false
+                                }
+                            })
+                        }
+                        None => parser,
                     }
-                    _ => false,
-                }),
+                }
+                &Spawn(shift, ref kind) => {
+                    let kind = kind.clone();
+                    Arc::new(move |state| state.spawn(&kind, shift))
+                }
+                &Symbol(symbol) => Arc::new(move |state| state.symbol(symbol)),
                 Text(text) => {
                     let text = text.clone();
-                    Rc::new(move |state| match state.text.get(state.index..) {
-                        Some(slice) if slice.starts_with(text.as_str()) => {
-                            state.index += text.len();
-                            true
-                        }
-                        _ => false,
-                    })
+                    Arc::new(move |state| state.text(&text))
                 }
-                &Store(shift, Stack::Push) => Rc::new(move |state| {
-                    state.indices.push(state.index - shift);
-                    true
-                }),
-                &Store(shift, Stack::Pop) => {
-                    let depth = state.depth;
-                    Rc::new(move |state| match state.indices.pop() {
-                        Some(index) => {
-                            let depth = state.values[depth];
-                            let value = &state.text[index..state.index - shift];
-                            state.stores.push((value, depth));
-                            true
-                        }
-                        None => false,
-                    })
+                &Store(shift, stack) => Arc::new(move |state| state.store(shift, stack)),
+                &Precede(precedence, bind, stack) => {
+                    Arc::new(move |state| state.precede(precedence, bind, stack))
                 }
-                &Precede(precedence, bind, Stack::Push) => Rc::new(move |state| match bind {
-                    Bind::Left if precedence <= state.precedence => false,
-                    Bind::Right if precedence < state.precedence => false,
-                    _ => {
-                        let precedence = mem::replace(&mut state.precedence, precedence);
-                        state.precedences.push(precedence);
-                        true
-                    }
-                }),
-                Precede(_, _, Stack::Pop) => Rc::new(move |state| match state.precedences.pop() {
-                    Some(precedence) => {
-                        state.precedence = precedence;
+                Set(Index(index), set) => {
+                    let (index, set) = (*index, set.clone());
+                    Arc::new(move |state| {
+                        state.set(index, set.apply(state.values[index], &state.values));
                         true
-                    }
-                    None => false,
-                }),
-                &Set(Index(index), Set::Copy(Index(copy))) => Rc::new(move |state| {
-                    state.values[index] = state.values[copy];
-                    true
-                }),
-                &Set(Index(index), Set::Add(value)) => Rc::new(move |state| {
-                    state.values[index] += value;
+                    })
+                }
+                &If(Index(left), compare, Index(right)) => {
+                    Arc::new(move |state| compare.compare(state.values[left], state.values[right]))
+                }
+                &Push(Index(index)) => Arc::new(move |state| {
+                    state.push(index);
                     true
                 }),
-                &Set(Index(index), Set::Value(value)) => Rc::new(move |state| {
-                    state.values[index] = value;
+                &Pop(Index(index)) => Arc::new(move |state| state.pop(index)),
+                &Integer(Index(index), radix) => Arc::new(move |state| state.integer(index, radix)),
+                Times(Index(index), node) => {
+                    let (index, parser) = (*index, next(node, state));
+                    // The count is read once, such that the node may change the slot.
+                    Arc::new(move |state| (0..state.values[index]).all(|_| parser(state)))
+                }
+                Native(slots, predicate) => {
+                    let indices: Vec<_> = slots
+                        .iter()
+                        .map(|slot| match slot {
+                            &Index(index) => index,
+                            slot => panic!("Invalid slot '{:?}'.", slot),
+                        })
+                        .collect();
+                    let predicate = predicate.clone();
+                    Arc::new(move |state| state.native(&indices, &predicate))
+                }
+                Capture(name) => {
+                    let name = name.clone();
+                    Arc::new(move |state| state.name(&name))
+                }
+                Label(name) => {
+                    let name = name.clone();
+                    Arc::new(move |state| state.label(&name))
+                }
+                &Field(ref name, stack) => {
+                    let name = name.clone();
+                    Arc::new(move |state| state.field(&name, stack))
+                }
+                Back(name) => {
+                    let name = name.clone();
+                    Arc::new(move |state| state.back(&name))
+                }
+                Cut => Arc::new(|state| {
+                    state.cut();
                     true
                 }),
-                &If(Index(left), If::Less, Index(right)) => {
-                    Rc::new(move |state| state.values[left] < state.values[right])
+                Peek(node) => {
+                    let parser = next(node, state);
+                    Arc::new(move |state| {
+                        let checkpoint = state.checkpoint();
+                        let cuts = state.cuts;
+                        let success = parser(state);
+                        state.restore(&checkpoint);
+                        state.release(checkpoint);
+                        state.cuts = cuts;
+                        success
+                    })
                 }
-                &If(Index(left), If::Equal, Index(right)) => {
-                    Rc::new(move |state| state.values[left] == state.values[right])
+                Not(node) => {
+                    let parser = next(node, state);
+                    Arc::new(move |state| {
+                        // What the node expected is not relevant when it must fail.
+                        let failure = state.failure.take();
+                        let checkpoint = state.checkpoint();
+                        let cuts = state.cuts;
+                        let success = parser(state);
+                        state.restore(&checkpoint);
+                        state.release(checkpoint);
+                        state.cuts = cuts;
+                        state.failure.replace(failure);
+                        !success
+                    })
+                }
+                Recover(node, sync) => {
+                    let parser = next(node, state);
+                    let sync = next(sync, state);
+                    Arc::new(move |state| {
+                        if state.choices.is_some() {
+                            return parser(state);
+                        }
+
+                        let failure = state.failure.replace((state.index, Vec::new()));
+                        let checkpoint = state.checkpoint();
+                        // Cuts do not escape a recovery.
+                        let cuts = state.cuts;
+                        let success = parser(state);
+                        state.cuts = cuts;
+                        let (index, expected) = state.failure.replace(failure);
+                        for expect in expected.iter() {
+                            state.expect(index, || expect.clone());
+                        }
+                        if !success {
+                            state.restore(&checkpoint);
+                        }
+                        state.release(checkpoint);
+                        if success {
+                            return true;
+                        }
+
+                        // Skip symbols until 'sync' succeeds without recording its failures.
+                        let failure = state.failure.take();
+                        let start = state.index;
+                        while state.index < state.text.len() && {
+                            let checkpoint = state.checkpoint();
+                            let success = sync(state);
+                            state.restore(&checkpoint);
+                            state.release(checkpoint);
+                            state.cuts = cuts;
+                            !success
+                        } {
+                            state.skip();
+                        }
+                        state.failure.replace(failure);
+                        if state.index == start {
+                            // Nothing was skipped such that the recovery would not progress.
+                            return false;
+                        }
+                        state.recovered(start, index, expected);
+                        true
+                    })
                 }
                 Switch(cases) => {
-                    let mut map = HashMap::new();
-                    for case in cases {
-                        map.insert(case.0, next(&case.1, state));
-                    }
+                    let mut cases: Vec<_> = cases.iter().collect();
+                    cases.sort_by_key(|case| *case.0.start());
+                    let keys: Vec<_> = cases.iter().map(|case| case.0.clone()).collect();
+                    let parsers: Vec<_> = cases.iter().map(|case| next(&case.1, state)).collect();
 
-                    Rc::new(move |state| {
-                        match state
-                            .text
-                            .get(state.index..)
-                            .and_then(|text| text.chars().next())
-                            .map(|key| (key, map.get(&key)))
+                    Arc::new(move |state| {
+                        let symbol = state.text[state.index..].chars().next();
+                        match symbol
+                            .and_then(|symbol| Some((symbol, class::search(&keys, symbol)?)))
                         {
-                            Some((key, Some(parser))) => {
-                                state.index += key.len_utf8();
-                                parser(state)
+                            Some((symbol, index)) => {
+                                state.index += symbol.len_utf8();
+                                parsers[index](state)
+                            }
+                            None => {
+                                state.expect_ranges(&keys);
+                                false
                             }
-                            _ => false,
                         }
                     })
                 }
+                Class(ranges, negate) => {
+                    let ranges = ranges.clone();
+                    let negate = *negate;
+                    Arc::new(move |state| state.class(&ranges, negate))
+                }
                 node => panic!("Invalid node '{}'.", node),
             }
         }
 
-        let (node, nodes, _, mut indices) = node.resolve();
+        let (node, nodes, rules, mut indices) = node.resolve();
         let depth = Path(".depth".into());
         let depth_index = match indices.get(&depth) {
             Some(index) => *index,
@@ -241,8 +1575,15 @@ impl From<Node> for Parser {
         };
 
         let mut state = State {
-            depth: depth_index,
             references: vec![None; nodes.len()],
+            rules: rules
+                .into_iter()
+                .filter_map(|(identifier, index)| match identifier {
+                    Path(path) => Some((index, path)),
+                    _ => None,
+                })
+                .collect(),
+            recursive: Node::recursive(&nodes),
         };
         for i in 0..nodes.len() {
             state.references[i] = Some(next(&nodes[i], &state));
@@ -257,18 +1598,145 @@ impl From<Node> for Parser {
             root,
             references,
             indices,
+            depth: depth_index,
+            memoize: 0,
+        }
+    }
+}
+
+impl Error {
+    pub fn new(text: &str, index: usize, expected: Vec<Expect>) -> Self {
+        let (line, column) = position(text, index);
+        Error {
+            index,
+            line,
+            column,
+            expected,
+        }
+    }
+}
+
+impl Display for Expect {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Expect::Symbol(symbol) => write!(formatter, "'{}'", symbol.escape_debug()),
+            Expect::Text(text) => write!(formatter, "\"{}\"", text.escape_debug()),
+            Expect::Rule(rule) => write!(formatter, "{}", rule),
+            Expect::Range(low, high) => write!(
+                formatter,
+                "'{}'..='{}'",
+                low.escape_debug(),
+                high.escape_debug()
+            ),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(formatter, "{}:{}: ", self.line, self.column)?;
+        if self.expected.is_empty() {
+            return formatter.write_str("unexpected input");
+        }
+        formatter.write_str("expected ")?;
+        let expected = self
+            .expected
+            .iter()
+            .map(|expect| expect.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        formatter.write_str(&expected)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl<'a> Tree<'a> {
+    /// First child named 'name' by a 'field'.
+    pub fn field(&self, name: &str) -> Option<&Tree<'a>> {
+        self.field_all(name).into_iter().next()
+    }
+
+    /// Children named 'name' by a 'field' in order, such that a repeated field is a list.
+    pub fn field_all(&self, name: &str) -> Vec<&Tree<'a>> {
+        self.fields
+            .iter()
+            .filter(|field| field.0 == name)
+            .filter_map(|field| self.children.get(field.1))
+            .collect()
+    }
+
+    /// First value named 'name' by a 'store_as'.
+    pub fn value(&self, name: &str) -> Option<&'a str> {
+        self.value_all(name).into_iter().next()
+    }
+
+    /// Values named 'name' by a 'store_as' in order, such that a repeated value is a list.
+    pub fn value_all(&self, name: &str) -> Vec<&'a str> {
+        self.labels
+            .iter()
+            .filter(|label| label.0 == name)
+            .filter_map(|label| self.values.get(label.1).copied())
+            .collect()
+    }
+}
+
+impl Tree<'_> {
+    /// Copies the values of the tree and its children such that they no longer borrow the source text.
+    pub fn into_owned(self) -> OwnedTree {
+        OwnedTree {
+            kind: self.kind,
+            span: self.span,
+            values: self.values.into_iter().map(String::from).collect(),
+            spans: self.spans,
+            children: self.children.into_iter().map(Tree::into_owned).collect(),
+            labels: self.labels,
+            fields: self.fields,
+        }
+    }
+}
+
+impl OwnedTree {
+    /// Borrows the values of the tree and its children.
+    pub fn as_tree(&self) -> Tree<'_> {
+        Tree {
+            kind: self.kind.clone(),
+            span: self.span.clone(),
+            values: self.values.iter().map(String::as_str).collect(),
+            spans: self.spans.clone(),
+            children: self.children.iter().map(OwnedTree::as_tree).collect(),
+            labels: self.labels.clone(),
+            fields: self.fields.clone(),
         }
     }
 }
 
+impl From<Tree<'_>> for OwnedTree {
+    fn from(tree: Tree) -> Self {
+        tree.into_owned()
+    }
+}
+
+impl Debug for OwnedTree {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        Display::fmt(self, formatter)
+    }
+}
+
+impl Display for OwnedTree {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        Display::fmt(&self.as_tree(), formatter)
+    }
+}
+
 impl Debug for Tree<'_> {
-    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), Error> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
         Display::fmt(self, formatter)
     }
 }
 
 impl Display for Tree<'_> {
-    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), Error> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result<(), fmt::Error> {
         formatter.write_str(&format!("{}", self.kind))?;
         if self.values.len() > 0 {
             let values = self
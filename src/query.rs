@@ -0,0 +1,228 @@
+use crate::parse::*;
+
+/// Iterates over a tree and its descendants where each tree comes before its children.
+pub struct PreOrder<'a, 'b> {
+    stack: Vec<&'b Tree<'a>>,
+}
+
+/// Iterates over a tree and its descendants where each tree comes after its children.
+pub struct PostOrder<'a, 'b> {
+    stack: Vec<(&'b Tree<'a>, bool)>,
+}
+
+/// Navigates a tree from its root where the current tree knows its parent and siblings.
+pub struct Cursor<'a, 'b> {
+    root: &'b Tree<'a>,
+    /// Ancestors of the current tree with the index of the child that leads to it.
+    path: Vec<(&'b Tree<'a>, usize)>,
+}
+
+/// Succeeds if 'kind' is 'family' or one of its members, such that '.b' matches '.b' and '.b.0' but not
+/// '.bc', like the paths of 'define'. The '*' family matches every kind.
+pub fn member(family: &str, kind: &str) -> bool {
+    family == "*"
+        || kind
+            .strip_prefix(family)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Parses a selector such as '.object > .string' into '(family, child)' steps where 'child' is set if the
+/// tree must be a child of the tree of the previous step rather than any of its descendants. Fails where a
+/// family is expected, which is at a '>' that follows no family or at the end of the selector.
+fn steps(selector: &str) -> Result<Vec<(&str, bool)>, Error> {
+    let family = || vec![Expect::Rule("family".into())];
+    let mut steps = Vec::new();
+    let mut child = false;
+    let mut start = None;
+    for (index, symbol) in selector.char_indices().chain([(selector.len(), ' ')]) {
+        let separator = symbol.is_whitespace() || symbol == '>';
+        match start {
+            Some(first) if separator => {
+                steps.push((&selector[first..index], child));
+                child = false;
+                start = None;
+            }
+            None if !separator => start = Some(index),
+            _ => {}
+        }
+        if symbol == '>' {
+            if child || steps.is_empty() {
+                return Err(Error::new(selector, index, family()));
+            }
+            child = true;
+        }
+    }
+    if child || steps.is_empty() {
+        return Err(Error::new(selector, selector.len(), family()));
+    }
+    Ok(steps)
+}
+
+/// Succeeds if the 'ancestors' of a tree, from the root to its parent, satisfy the 'steps' where the last
+/// step is the one that the tree matched.
+fn satisfies(steps: &[(&str, bool)], ancestors: &[&Tree]) -> bool {
+    let (child, steps) = match steps.split_last() {
+        Some((step, steps)) => (step.1, steps),
+        None => return true,
+    };
+    let family = match steps.last() {
+        Some(step) => step.0,
+        None => return true,
+    };
+    let start = if child {
+        ancestors.len().saturating_sub(1)
+    } else {
+        0
+    };
+    (start..ancestors.len()).rev().any(|index| {
+        member(family, &ancestors[index].kind) && satisfies(steps, &ancestors[..index])
+    })
+}
+
+impl<'a> Tree<'a> {
+    pub fn pre_order<'b>(&'b self) -> PreOrder<'a, 'b> {
+        PreOrder { stack: vec![self] }
+    }
+
+    pub fn post_order<'b>(&'b self) -> PostOrder<'a, 'b> {
+        PostOrder {
+            stack: vec![(self, false)],
+        }
+    }
+
+    /// Finds the first tree in pre-order, including this one, whose kind is a member of 'family'.
+    pub fn find<'b>(&'b self, family: &str) -> Option<&'b Tree<'a>> {
+        self.pre_order().find(|tree| member(family, &tree.kind))
+    }
+
+    /// Finds the trees in pre-order, including this one, whose kind is a member of 'family'.
+    pub fn find_all<'b, 'c>(&'b self, family: &'c str) -> impl Iterator<Item = &'b Tree<'a>> + 'c
+    where
+        'b: 'c,
+    {
+        self.pre_order()
+            .filter(move |tree| member(family, &tree.kind))
+    }
+
+    /// Selects the trees in pre-order, including this one, that match 'selector'. A selector is a sequence
+    /// of families separated by white space, which matches a descendant of the tree of the previous family,
+    /// or by '>', which matches a child. Fails if the selector is empty or misplaces a '>'.
+    pub fn select<'b>(&'b self, selector: &str) -> Result<Vec<&'b Tree<'a>>, Error> {
+        fn next<'a, 'b>(
+            tree: &'b Tree<'a>,
+            steps: &[(&str, bool)],
+            path: &mut Vec<&'b Tree<'a>>,
+            trees: &mut Vec<&'b Tree<'a>>,
+        ) {
+            let (family, _) = steps[steps.len() - 1];
+            if member(family, &tree.kind) && satisfies(steps, path) {
+                trees.push(tree);
+            }
+            path.push(tree);
+            for child in tree.children.iter() {
+                next(child, steps, path, trees);
+            }
+            path.pop();
+        }
+
+        let steps = steps(selector)?;
+        let mut trees = Vec::new();
+        next(self, &steps, &mut Vec::new(), &mut trees);
+        Ok(trees)
+    }
+
+    pub fn cursor<'b>(&'b self) -> Cursor<'a, 'b> {
+        Cursor {
+            root: self,
+            path: Vec::new(),
+        }
+    }
+}
+
+impl<'a, 'b> Cursor<'a, 'b> {
+    pub fn tree(&self) -> &'b Tree<'a> {
+        match self.path.last() {
+            Some(&(parent, index)) => &parent.children[index],
+            None => self.root,
+        }
+    }
+
+    /// Number of ancestors of the current tree.
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    /// Index of the current tree among its siblings.
+    pub fn index(&self) -> Option<usize> {
+        self.path.last().map(|pair| pair.1)
+    }
+
+    pub fn parent(&mut self) -> bool {
+        self.path.pop().is_some()
+    }
+
+    pub fn child(&mut self, index: usize) -> bool {
+        let tree = self.tree();
+        if index < tree.children.len() {
+            self.path.push((tree, index));
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn first_child(&mut self) -> bool {
+        self.child(0)
+    }
+
+    pub fn last_child(&mut self) -> bool {
+        let count = self.tree().children.len();
+        count > 0 && self.child(count - 1)
+    }
+
+    pub fn next_sibling(&mut self) -> bool {
+        match self.path.last_mut() {
+            Some((parent, index)) if *index + 1 < parent.children.len() => {
+                *index += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn previous_sibling(&mut self) -> bool {
+        match self.path.last_mut() {
+            Some((_, index)) if *index > 0 => {
+                *index -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a, 'b> Iterator for PreOrder<'a, 'b> {
+    type Item = &'b Tree<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.stack.pop()?;
+        self.stack.extend(tree.children.iter().rev());
+        Some(tree)
+    }
+}
+
+impl<'a, 'b> Iterator for PostOrder<'a, 'b> {
+    type Item = &'b Tree<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (tree, visited) = self.stack.pop()?;
+            if visited {
+                return Some(tree);
+            }
+            self.stack.push((tree, true));
+            self.stack
+                .extend(tree.children.iter().rev().map(|child| (child, false)));
+        }
+    }
+}
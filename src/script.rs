@@ -0,0 +1,82 @@
+use crate::class;
+use crate::convert::*;
+use crate::node::*;
+use crate::parse::*;
+use crate::unparse::*;
+use crate::*;
+use std::sync::OnceLock;
+
+/// A statement of an indentation-sensitive language where blocks are nested by indentation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Syntax {
+    Name(String),
+    If(String, Vec<Syntax>),
+    While(String, Vec<Syntax>),
+}
+
+pub fn converter() -> Converter<Syntax> {
+    Converter::new()
+        .on(".name", |tree, _| {
+            Some(Syntax::Name((*tree.values.first()?).into()))
+        })
+        .on(".if", |tree, block| {
+            Some(Syntax::If((*tree.values.first()?).into(), block))
+        })
+        .on(".while", |tree, block| {
+            Some(Syntax::While((*tree.values.first()?).into(), block))
+        })
+}
+
+/// Converts 'tree' with a converter that is built once.
+pub fn convert(tree: &Tree) -> Option<Syntax> {
+    static CONVERTER: OnceLock<Converter<Syntax>> = OnceLock::new();
+    CONVERTER.get_or_init(converter).convert(tree).ok()
+}
+
+pub fn node() -> Node {
+    let block = || {
+        all!(
+            ':',
+            &"~",
+            indent(),
+            join(all!(&"~", same_indent()), refer("")),
+            dedent()
+        )
+    };
+    all!(
+        // Blank lines are skipped such that the indentation is parsed at the start of the next line.
+        define("~", repeat(1.., all!(repeat(.., any!(' ', '\t')), '\n'))),
+        define(
+            "~name",
+            store(all!(class::xid_start(), repeat(.., class::xid_continue())))
+        ),
+        syntax(".if", all!("if ", &"~name", block())),
+        syntax(".while", all!("while ", &"~name", block())),
+        syntax(".name", refer("~name")),
+    )
+}
+
+/// Statements of a whole text, which may start and end with blank lines.
+fn root() -> Node {
+    all!(
+        option(refer("~")),
+        same_indent(),
+        join(all!(&"~", same_indent()), refer("")),
+        option(refer("~")),
+        repeat(.., any!(' ', '\t')),
+        node()
+    )
+}
+
+pub fn parser() -> Parser {
+    Parser::from(root())
+}
+
+pub fn unparser() -> Unparser {
+    Unparser::from(root())
+}
+
+pub fn parse(text: &str) -> Option<Vec<Syntax>> {
+    let trees = parser().try_parse(text).ok()?;
+    trees.iter().map(convert).collect()
+}
@@ -0,0 +1,558 @@
+use crate::class;
+use crate::node::Set;
+use crate::node::*;
+use crate::parse::*;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::slice;
+use Identifier::*;
+use Node::*;
+
+/// Prints trees back to text with the grammar that parsed them. The grammar is walked with backtracking
+/// where the alternatives that can print the rest of the tree and then those that print the least text are
+/// tried first, the stores print the values of the tree and the syntaxes print its children in order. The
+/// texts whose shape matches the trees are verified by parsing them back once the search ends.
+///
+/// Outside of stores, an alternative that can not reach a store and that does not print the least text,
+/// such as more white space, costs one. Once a text is printed, only the texts that cost less are searched
+/// further, such that trivia is minimal.
+#[derive(Clone)]
+pub struct Unparser {
+    root: Node,
+    definitions: Vec<Node>,
+    /// Indices of the definitions that can reach a store.
+    productive: HashSet<usize>,
+    slots: usize,
+    /// Index of the '.depth' value, which is incremented at the start of a syntax.
+    depth: Option<usize>,
+    parser: Parser,
+}
+
+/// A tree being printed with the number of its values and children that were printed.
+#[derive(Clone, Copy)]
+struct Frame<'a, 'b> {
+    kind: &'b str,
+    values: &'b [&'a str],
+    children: &'b [Tree<'a>],
+    labels: &'b [(String, usize)],
+    fields: &'b [(String, usize)],
+    value: usize,
+    child: usize,
+}
+
+struct State<'a, 'b> {
+    text: String,
+    frames: Vec<Frame<'a, 'b>>,
+    /// Value of the open store with the start of its text.
+    store: Option<(&'a str, usize)>,
+    /// Text of the last store.
+    last: &'a str,
+    values: Vec<isize>,
+    saves: Vec<(usize, isize)>,
+    captures: Vec<((String, &'a str), usize)>,
+    /// Numbers of printed children of the current tree when the open fields started.
+    marks: Vec<usize>,
+    /// Number of references entered since the last progress in the tree, which bounds recursion. Printing
+    /// a value and starting or closing a tree are progress.
+    refers: usize,
+    /// Cost of the costly alternatives taken so far.
+    cost: usize,
+    /// Maximum cost of the texts that remain to be searched.
+    bound: usize,
+}
+
+/// A point to which a 'State' can backtrack.
+struct Checkpoint<'a, 'b> {
+    length: usize,
+    frames: Vec<Frame<'a, 'b>>,
+    store: Option<(&'a str, usize)>,
+    last: &'a str,
+    values: Vec<isize>,
+    saves: Vec<(usize, isize)>,
+    captures: Vec<((String, &'a str), usize)>,
+    marks: Vec<usize>,
+    refers: usize,
+    cost: usize,
+}
+
+/// A node that remains to be printed, followed by the ones that remain after it.
+struct Pending<'c> {
+    node: &'c Node,
+    next: Option<Rc<Pending<'c>>>,
+}
+
+impl<'c> Pending<'c> {
+    fn push(node: &'c Node, next: Option<Rc<Pending<'c>>>) -> Option<Rc<Pending<'c>>> {
+        Some(Rc::new(Pending { node, next }))
+    }
+}
+
+/// The alternatives of an 'Or' that remain to be tried, in reverse order with their positions in the
+/// cheapest order, and the state and the nodes that each of them starts from.
+struct Choice<'a, 'b, 'c> {
+    nodes: Vec<(usize, &'c Node)>,
+    checkpoint: Checkpoint<'a, 'b>,
+    pending: Option<Rc<Pending<'c>>>,
+}
+
+/// Succeeds if the trees have the same kinds, values, children and names regardless of their spans.
+fn same(left: &Tree, right: &Tree) -> bool {
+    left.kind == right.kind
+        && left.values == right.values
+        && left.labels == right.labels
+        && left.fields == right.fields
+        && left.children.len() == right.children.len()
+        && left
+            .children
+            .iter()
+            .zip(right.children.iter())
+            .all(|(left, right)| same(left, right))
+}
+
+impl<'a, 'b> State<'a, 'b> {
+    fn checkpoint(&self) -> Checkpoint<'a, 'b> {
+        Checkpoint {
+            length: self.text.len(),
+            frames: self.frames.clone(),
+            store: self.store,
+            last: self.last,
+            values: self.values.clone(),
+            saves: self.saves.clone(),
+            captures: self.captures.clone(),
+            marks: self.marks.clone(),
+            refers: self.refers,
+            cost: self.cost,
+        }
+    }
+
+    fn restore(&mut self, checkpoint: &Checkpoint<'a, 'b>) {
+        self.text.truncate(checkpoint.length);
+        self.frames.clone_from(&checkpoint.frames);
+        self.store = checkpoint.store;
+        self.last = checkpoint.last;
+        self.values.clone_from(&checkpoint.values);
+        self.saves.clone_from(&checkpoint.saves);
+        self.captures.clone_from(&checkpoint.captures);
+        self.marks.clone_from(&checkpoint.marks);
+        self.refers = checkpoint.refers;
+        self.cost = checkpoint.cost;
+    }
+
+    /// Text of the value of the open store that remains to be printed.
+    fn remaining(&self) -> Option<&'a str> {
+        self.store
+            .map(|(value, start)| value.get(self.text.len() - start..).unwrap_or(""))
+    }
+
+    /// Prints 'symbol' if the open store expects it, which is progress in the tree.
+    fn print(&mut self, symbol: char) -> bool {
+        match self.remaining() {
+            Some(remaining) if !remaining.starts_with(symbol) => false,
+            Some(_) => {
+                self.text.push(symbol);
+                self.refers = 0;
+                true
+            }
+            None => {
+                self.text.push(symbol);
+                true
+            }
+        }
+    }
+}
+
+impl Unparser {
+    /// Maximum number of references entered without progress in the tree.
+    const DEPTH: usize = 64;
+    /// Maximum cost of a printed text.
+    const BUDGET: usize = 8;
+
+    /// Prints 'tree' as text that parses back into it, or 'None' if the grammar has no such text.
+    pub fn print(&self, tree: &Tree) -> Option<String> {
+        self.print_all(slice::from_ref(tree))
+    }
+
+    /// Prints 'trees' as text that parses back into them.
+    pub fn print_all(&self, trees: &[Tree]) -> Option<String> {
+        let mut state = State {
+            text: String::new(),
+            frames: vec![Frame {
+                kind: "",
+                values: &[],
+                children: trees,
+                labels: &[],
+                fields: &[],
+                value: 0,
+                child: 0,
+            }],
+            store: None,
+            last: "",
+            values: vec![0; self.slots],
+            saves: Vec::new(),
+            captures: Vec::new(),
+            marks: Vec::new(),
+            refers: 0,
+            cost: 0,
+            bound: Self::BUDGET,
+        };
+        // The texts are found from the most to the least costly, and only the cheapest one that parses back
+        // into the trees is needed, which is usually the last one.
+        let texts = self.search(&mut state, trees.len());
+        texts
+            .into_iter()
+            .rev()
+            .find(|text| match self.parser.try_parse(text) {
+                Ok(parsed) => {
+                    parsed.len() == trees.len()
+                        && parsed
+                            .iter()
+                            .zip(trees.iter())
+                            .all(|(left, right)| same(left, right))
+                }
+                Err(_) => false,
+            })
+    }
+
+    /// Succeeds if 'node' can reach a store.
+    fn productive(&self, node: &Node) -> bool {
+        match node {
+            Store(_, Stack::Push) => true,
+            Refer(Index(index)) => self.productive.contains(index),
+            And(left, right) | Or(left, right) => self.productive(left) || self.productive(right),
+            Recover(node, _) | Times(_, node) => self.productive(node),
+            _ => false,
+        }
+    }
+
+    /// Minimum number of characters that 'node' prints, where references are assumed to print none.
+    fn length(node: &Node) -> usize {
+        match node {
+            Symbol(_) | Class(_, _) | Integer(_, _) => 1,
+            And(left, right) => Self::length(left) + Self::length(right),
+            Or(left, right) => Self::length(left).min(Self::length(right)),
+            Recover(node, _) => Self::length(node),
+            _ => 0,
+        }
+    }
+
+    /// Walks the grammar from the root with an explicit stack of the nodes that remain to be printed and of
+    /// the choices to backtrack to, and returns the texts that print the 'count' trees of the root frame
+    /// in the order they are found, each one cheaper than the previous one.
+    fn search<'a, 'b>(&self, state: &mut State<'a, 'b>, count: usize) -> Vec<String> {
+        let mut texts = Vec::new();
+        let mut pending = Pending::push(&self.root, None);
+        let mut choices = Vec::new();
+        loop {
+            let success = match pending.take() {
+                Some(top) => {
+                    pending = top.next.clone();
+                    self.step(top.node, state, &mut pending, &mut choices)
+                }
+                // The shape of the trees is verified here and the texts are parsed back once the search ends.
+                None if state.frames.len() == 1 && state.frames[0].child == count => {
+                    texts.push(state.text.clone());
+                    // The search goes on for a cheaper text, unless this one costs nothing.
+                    match state.cost.checked_sub(1) {
+                        Some(bound) => state.bound = bound,
+                        None => return texts,
+                    }
+                    false
+                }
+                None => false,
+            };
+            if !success && !self.backtrack(state, &mut pending, &mut choices) {
+                return texts;
+            }
+        }
+    }
+
+    /// Prints 'node' and pushes what remains of it on 'pending'. An 'Or' pushes a choice and continues with
+    /// its first alternative.
+    fn step<'a, 'b, 'c>(
+        &'c self,
+        node: &'c Node,
+        state: &mut State<'a, 'b>,
+        pending: &mut Option<Rc<Pending<'c>>>,
+        choices: &mut Vec<Choice<'a, 'b, 'c>>,
+    ) -> bool {
+        match node {
+            True => true,
+            And(_, _) => self.sequence(&node.flatten(), state, pending),
+            Or(_, _) => {
+                choices.push(self.choose(node, state, pending.clone()));
+                self.backtrack(state, pending, choices)
+            }
+            Refer(Index(index)) => match self.definitions.get(*index) {
+                Some(node) if state.refers < Self::DEPTH => {
+                    state.refers += 1;
+                    *pending = Pending::push(node, pending.take());
+                    true
+                }
+                _ => false,
+            },
+            &Symbol(symbol) => state.print(symbol),
+            Class(ranges, negate) => {
+                let symbol = match state.remaining() {
+                    Some(remaining) => remaining.chars().next(),
+                    None if *negate => class::complement(ranges)
+                        .first()
+                        .map(|range| *range.start()),
+                    None => ranges.first().map(|range| *range.start()),
+                };
+                match symbol {
+                    Some(symbol) if class::search(ranges, symbol).is_some() != *negate => {
+                        state.print(symbol)
+                    }
+                    _ => false,
+                }
+            }
+            // A store in a store would print its value before the value of the enclosing store.
+            Store(0, Stack::Push) if state.store.is_none() => {
+                let frame = match state.frames.last_mut() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                match frame.values.get(frame.value) {
+                    Some(&value) => {
+                        frame.value += 1;
+                        state.store = Some((value, state.text.len()));
+                        state.refers = 0;
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Store(0, Stack::Pop) => match state.store {
+                Some((value, start)) if state.text.len() - start == value.len() => {
+                    state.store = None;
+                    state.last = value;
+                    true
+                }
+                _ => false,
+            },
+            Spawn(0, kind) => match state.frames.pop() {
+                Some(frame)
+                    if frame.kind == kind
+                        && frame.value == frame.values.len()
+                        && frame.child == frame.children.len() =>
+                {
+                    let depth = state.frames.len();
+                    state.captures.retain(|capture| capture.1 <= depth);
+                    state.refers = 0;
+                    true
+                }
+                _ => false,
+            },
+            Capture(name) => {
+                let depth = state.frames.len();
+                state.captures.push(((name.clone(), state.last), depth));
+                true
+            }
+            // A name applies to the value or the child that was printed last.
+            Label(name) => state.frames.last().is_some_and(|frame| {
+                frame.value > 0 && frame.labels.contains(&(name.clone(), frame.value - 1))
+            }),
+            Field(_, Stack::Push) => {
+                let child = state.frames.last().map_or(0, |frame| frame.child);
+                state.marks.push(child);
+                true
+            }
+            // A field applies to the child that was printed last if it was printed since the mark.
+            Field(name, Stack::Pop) => match (state.marks.pop(), state.frames.last()) {
+                (Some(mark), Some(frame)) if frame.child > mark => {
+                    frame.fields.contains(&(name.clone(), frame.child - 1))
+                }
+                (Some(_), Some(_)) => true,
+                _ => false,
+            },
+            Back(name) => {
+                let depth = state.frames.len();
+                let capture = state
+                    .captures
+                    .iter()
+                    .rev()
+                    .find(|capture| &capture.0 .0 == name && capture.1 <= depth);
+                match capture {
+                    Some(&((_, text), _)) => text.chars().all(|symbol| state.print(symbol)),
+                    None => false,
+                }
+            }
+            Set(Index(index), set) => {
+                state.values[*index] = set.apply(state.values[*index], &state.values);
+                true
+            }
+            &If(Index(left), compare, Index(right)) => {
+                compare.compare(state.values[left], state.values[right])
+            }
+            &Push(Index(index)) => {
+                state.saves.push((index, state.values[index]));
+                true
+            }
+            &Pop(Index(index)) => match state.saves.pop() {
+                Some((slot, value)) if slot == index => {
+                    state.values[index] = value;
+                    true
+                }
+                _ => false,
+            },
+            &Integer(Index(index), radix) => {
+                let digits = match state.remaining() {
+                    Some(remaining) => remaining
+                        .chars()
+                        .take_while(|symbol| symbol.is_digit(radix))
+                        .collect(),
+                    None => String::from("0"),
+                };
+                let value = digits.chars().try_fold(0isize, |value, symbol| {
+                    let digit = symbol.to_digit(radix).unwrap_or(0) as isize;
+                    value.checked_mul(radix as isize)?.checked_add(digit)
+                });
+                match value {
+                    Some(value) if !digits.is_empty() => {
+                        state.values[index] = value;
+                        digits.chars().all(|symbol| state.print(symbol))
+                    }
+                    _ => false,
+                }
+            }
+            Times(Index(index), node) => {
+                for _ in 0..state.values[*index] {
+                    *pending = Pending::push(node, pending.take());
+                }
+                true
+            }
+            Recover(node, _) => {
+                *pending = Pending::push(node, pending.take());
+                true
+            }
+            // Precedences, lookaheads and predicates are verified by parsing the printed text.
+            Precede(_, _, _) | Depth(_) | Cut | Peek(_) | Not(_) | Native(_, _) => true,
+            _ => false,
+        }
+    }
+
+    /// Orders the alternatives of the 'Or' chain 'node' into a choice that continues with 'pending'.
+    fn choose<'a, 'b, 'c>(
+        &self,
+        node: &'c Node,
+        state: &State<'a, 'b>,
+        pending: Option<Rc<Pending<'c>>>,
+    ) -> Choice<'a, 'b, 'c> {
+        let mut nodes = node.flatten();
+        nodes.retain(|node| !matches!(node, False));
+        nodes.sort_by_key(|node| (Self::length(node), !matches!(node, True)));
+        let mut nodes: Vec<_> = nodes.into_iter().enumerate().collect();
+        // While the tree has values or children left, the alternatives that can print them are tried first.
+        let pending_tree = state.store.is_none()
+            && state.frames.last().is_some_and(|frame| {
+                frame.value < frame.values.len() || frame.child < frame.children.len()
+            });
+        if pending_tree {
+            nodes.sort_by_key(|(_, node)| !self.productive(node));
+        }
+        nodes.reverse();
+        Choice {
+            nodes,
+            checkpoint: state.checkpoint(),
+            pending,
+        }
+    }
+
+    /// Restores the state of the last choice and continues with its next alternative, or with the next
+    /// alternative of an earlier choice once it has none left. Fails once no choice is left.
+    fn backtrack<'a, 'b, 'c>(
+        &self,
+        state: &mut State<'a, 'b>,
+        pending: &mut Option<Rc<Pending<'c>>>,
+        choices: &mut Vec<Choice<'a, 'b, 'c>>,
+    ) -> bool {
+        while let Some(choice) = choices.last_mut() {
+            state.restore(&choice.checkpoint);
+            while let Some((index, node)) = choice.nodes.pop() {
+                let costly = index > 0 && state.store.is_none() && !self.productive(node);
+                if costly && state.cost >= state.bound {
+                    continue;
+                }
+                if costly {
+                    state.cost += 1;
+                }
+                *pending = Pending::push(node, choice.pending.clone());
+                return true;
+            }
+            choices.pop();
+        }
+        false
+    }
+
+    /// Pushes the 'nodes' of a sequence on 'pending' where a 'Store' push followed by an increment of the
+    /// '.depth' starts the syntax of the next child of the current tree.
+    fn sequence<'a, 'b, 'c>(
+        &self,
+        nodes: &[&'c Node],
+        state: &mut State<'a, 'b>,
+        pending: &mut Option<Rc<Pending<'c>>>,
+    ) -> bool {
+        let nodes = match nodes {
+            [Store(0, Stack::Push), Set(Index(depth), Set::Add(1)), ..]
+                if Some(*depth) == self.depth =>
+            {
+                let frame = match state.frames.last_mut() {
+                    Some(frame) => frame,
+                    None => return false,
+                };
+                let tree = match frame.children.get(frame.child) {
+                    Some(tree) => tree,
+                    None => return false,
+                };
+                let spawn = nodes.iter().rev().find(|node| !matches!(node, True));
+                if let Some(Spawn(_, kind)) = spawn {
+                    if kind != &tree.kind {
+                        return false;
+                    }
+                }
+                frame.child += 1;
+                state.frames.push(Frame {
+                    kind: &tree.kind,
+                    values: &tree.values,
+                    children: &tree.children,
+                    labels: &tree.labels,
+                    fields: &tree.fields,
+                    value: 0,
+                    child: 0,
+                });
+                state.refers = 0;
+                &nodes[1..]
+            }
+            nodes => nodes,
+        };
+        for node in nodes.iter().rev() {
+            *pending = Pending::push(node, pending.take());
+        }
+        true
+    }
+}
+
+impl From<Node> for Unparser {
+    fn from(node: Node) -> Unparser {
+        let parser = Parser::from(node.clone());
+        // The definitions and the slots are collected like the parser does, before any optimization.
+        let (root, definitions, _, slots) = node.identify();
+        let mut unparser = Unparser {
+            root,
+            definitions,
+            productive: HashSet::new(),
+            slots: slots.len(),
+            depth: slots.get(&Path(".depth".into())).copied(),
+            parser,
+        };
+        loop {
+            let productive: HashSet<_> = (0..unparser.definitions.len())
+                .filter(|&index| unparser.productive(&unparser.definitions[index]))
+                .collect();
+            if productive.len() == unparser.productive.len() {
+                return unparser;
+            }
+            unparser.productive = productive;
+        }
+    }
+}
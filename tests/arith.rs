@@ -1,5 +1,7 @@
 extern crate quint;
 use quint::arith::*;
+use quint::node::*;
+use quint::parse::*;
 
 fn test(text: &str, syntax: Syntax) {
     assert_eq!(syntax, parse(text).unwrap());
@@ -19,17 +21,53 @@ fn add() {
         r#"1+2+3"#,
         Syntax::Binary(
             Binary::Add,
-            Syntax::Number(1).into(),
             Syntax::Binary(
                 Binary::Add,
+                Syntax::Number(1).into(),
                 Syntax::Number(2).into(),
-                Syntax::Number(3).into(),
             )
             .into(),
+            Syntax::Number(3).into(),
         ),
     );
     assert_eq!(true, parse(r#"1+"#).is_none());
-    assert_eq!(true, parse(r#"+1"#).is_none());
+    assert_eq!(true, parse(r#"*1"#).is_none());
+}
+
+#[test]
+fn precedence() {
+    let number = |value| Box::new(Syntax::Number(value));
+    test(
+        r#"1-2*3"#,
+        Syntax::Binary(
+            Binary::Subtract,
+            number(1),
+            Syntax::Binary(Binary::Multiply, number(2), number(3)).into(),
+        ),
+    );
+    test(
+        r#"-1++"#,
+        Syntax::Unary(
+            Unary::Negate,
+            Syntax::Unary(Unary::PostIncrement, number(1)).into(),
+        ),
+    );
+    test(
+        r#"1++2"#,
+        Syntax::Binary(
+            Binary::Add,
+            number(1),
+            Syntax::Unary(Unary::Absolute, number(2)).into(),
+        ),
+    );
+    test(
+        r#"(1+2)/3"#,
+        Syntax::Binary(
+            Binary::Divide,
+            Syntax::Binary(Binary::Add, number(1), number(2)).into(),
+            number(3),
+        ),
+    );
 }
 
 #[test]
@@ -66,3 +104,19 @@ fn generate_mixed() {
         parse(&text).unwrap();
     }
 }
+
+#[test]
+fn reduce_binary() {
+    let parser = Parser::from(and(&"", node()));
+    for text in ["1*2-3/4", "1-2-3", "(1+2)*-3++"] {
+        let syntaxes = parser.reduce(text, &actions()).unwrap();
+        let trees = parser.try_parse(text).unwrap();
+        assert_eq!(syntaxes, [convert(&trees[0]).unwrap()]);
+    }
+    let number = |value| Box::new(Syntax::Number(value));
+    let left = Syntax::Binary(Binary::Subtract, number(1), number(2));
+    assert_eq!(
+        parser.reduce("1-2-3", &actions()).unwrap(),
+        [Syntax::Binary(Binary::Subtract, left.into(), number(3))]
+    );
+}
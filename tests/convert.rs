@@ -0,0 +1,183 @@
+extern crate quint;
+use quint::convert::*;
+use quint::node::*;
+use quint::parse::*;
+use quint::*;
+use quint::{arith, json, script};
+
+const TEXT: &str = r#"{ "a": [1, null], "b": { "c": true } }"#;
+
+struct Kinds(Vec<String>);
+
+impl TreeVisitor<'_> for Kinds {
+    fn enter(&mut self, tree: &Tree) -> bool {
+        self.0.push(format!("+{}", tree.kind));
+        tree.kind != ".array"
+    }
+
+    fn exit(&mut self, tree: &Tree) {
+        self.0.push(format!("-{}", tree.kind));
+    }
+}
+
+struct Height;
+
+impl TreeFold<'_> for Height {
+    type Output = usize;
+
+    fn fold(&mut self, _: &Tree, children: Vec<usize>) -> usize {
+        children.into_iter().max().map_or(1, |height| height + 1)
+    }
+}
+
+#[test]
+fn visit() {
+    let trees = json::parser()
+        .try_parse(r#"{ "a": [1], "b": null }"#)
+        .unwrap();
+    let mut kinds = Kinds(Vec::new());
+    trees[0].visit(&mut kinds);
+    assert_eq!(
+        kinds.0,
+        [
+            "+.object", "+.string", "-.string", "+.array", "-.array", "+.string", "-.string",
+            "+.null", "-.null", "-.object"
+        ]
+    );
+}
+
+#[test]
+fn fold() {
+    let trees = json::parser().try_parse(TEXT).unwrap();
+    assert_eq!(trees[0].fold(&mut Height), 3);
+    let trees = json::parser().try_parse("[[[[]]]]").unwrap();
+    assert_eq!(trees[0].fold(&mut Height), 4);
+}
+
+#[test]
+fn unhandled_kind() {
+    let trees = json::parser().try_parse(TEXT).unwrap();
+    let converter = json::converter().on(".null", |_, _| None);
+    let failure = converter.convert(&trees[0]).unwrap_err();
+    assert_eq!(
+        failure.path,
+        [
+            (0, ".object".to_string()),
+            (1, ".array".to_string()),
+            (1, ".null".to_string())
+        ]
+    );
+    assert_eq!(&TEXT[failure.span.clone()], "null");
+    assert!(!failure.unhandled);
+    assert_eq!(
+        failure.to_string(),
+        "Invalid tree at '.object[0] > .array[1] > .null[1]'."
+    );
+
+    let converter = Converter::new().on(".object", |_, _| Some(()));
+    let failure = converter.convert(&trees[0]).unwrap_err();
+    assert!(failure.unhandled);
+    assert_eq!(
+        failure.to_string(),
+        "Unhandled kind at '.object[0] > .string[0]'."
+    );
+}
+
+#[test]
+fn families() {
+    let node = all!(
+        repeat(1.., &".b"),
+        syntax(".b.0", store('0')),
+        syntax(".b.1", store('1')),
+        syntax(".c", store('2')),
+    );
+    let converter = Converter::new()
+        .on(".b", |tree, _| Some(tree.values[0].to_string()))
+        .on(".b.1", |_, _| Some("one".to_string()));
+    let trees = Parser::from(node.clone()).try_parse("0110").unwrap();
+    let values: Vec<_> = trees
+        .iter()
+        .map(|tree| converter.convert(tree).unwrap())
+        .collect();
+    assert_eq!(values, ["0", "one", "one", "0"]);
+    assert_eq!(converter.unhandled(&node), [".c"]);
+}
+
+#[test]
+fn exhaustive() {
+    assert!(json::converter().unhandled(&json::node()).is_empty());
+    assert!(arith::converter().unhandled(&arith::node()).is_empty());
+    assert!(script::converter().unhandled(&script::node()).is_empty());
+    assert_eq!(
+        Converter::<()>::new().unhandled(&json::node()),
+        [".array", ".false", ".null", ".number", ".object", ".string", ".true"]
+    );
+}
+
+#[test]
+fn missing_values() {
+    // Trees built by hand may lack the values that a handler reads, which fails the conversion.
+    let tree = |kind: &str| Tree {
+        kind: kind.into(),
+        ..Default::default()
+    };
+    assert_eq!(json::convert(&tree(".number")), None);
+    assert_eq!(json::convert(&tree(".string")), None);
+    assert_eq!(arith::convert(&tree("pre.number")), None);
+    assert_eq!(script::convert(&tree(".name")), None);
+    assert_eq!(script::convert(&tree(".if")), None);
+}
+
+#[test]
+fn binary_fields() {
+    // The operands are the 'left' and 'right' fields whatever their positions among the children.
+    let number = |value: &'static str| Tree {
+        kind: "pre.number".into(),
+        values: vec![value],
+        ..Default::default()
+    };
+    let tree = |fields: Vec<(&str, usize)>| Tree {
+        kind: "post.subtract".into(),
+        children: vec![number("1"), number("2")],
+        fields: fields
+            .into_iter()
+            .map(|(name, index)| (name.to_string(), index))
+            .collect(),
+        ..Default::default()
+    };
+    let subtract = |left, right| {
+        arith::Syntax::Binary(
+            arith::Binary::Subtract,
+            arith::Syntax::Number(left).into(),
+            arith::Syntax::Number(right).into(),
+        )
+    };
+    let fields = |left, right| vec![("left", left), ("right", right)];
+    assert_eq!(arith::convert(&tree(fields(0, 1))), Some(subtract(1, 2)));
+    assert_eq!(arith::convert(&tree(fields(1, 0))), Some(subtract(2, 1)));
+    assert_eq!(arith::convert(&tree(fields(1, 1))), None);
+    assert_eq!(arith::convert(&tree(vec![("right", 1)])), None);
+    assert_eq!(arith::convert(&tree(Vec::new())), None);
+}
+
+#[test]
+fn binary_parsed() {
+    let trees = Parser::from(and(&"", arith::node())).parse("1+2*3");
+    let add = &trees[0];
+    let multiply = add.field("right").unwrap();
+    assert_eq!("post.add", add.kind);
+    assert_eq!(["1"], add.field("left").unwrap().values[..]);
+    assert_eq!("post.multiply", multiply.kind);
+    assert_eq!(["2"], multiply.field("left").unwrap().values[..]);
+    assert_eq!(["3"], multiply.field("right").unwrap().values[..]);
+    let number = |value| Box::new(arith::Syntax::Number(value));
+    let product = arith::Syntax::Binary(arith::Binary::Multiply, number(2), number(3));
+    assert_eq!(
+        arith::convert(add),
+        Some(arith::Syntax::Binary(
+            arith::Binary::Add,
+            number(1),
+            product.into()
+        ))
+    );
+}
@@ -0,0 +1,116 @@
+extern crate quint;
+use quint::cst::*;
+use quint::node::*;
+use quint::parse::*;
+use quint::*;
+use quint::{arith, json, script};
+
+/// Verifies that the leaves of the 'csts' cover the 'text' exactly once and in order.
+fn lossless(text: &str, csts: &[Cst]) {
+    let mut index = 0;
+    for leaf in csts.iter().flat_map(Cst::leaves) {
+        let span = leaf.span();
+        assert_eq!(span.start, index, "{:?}", csts);
+        index = span.end;
+    }
+    assert_eq!(index, text.len());
+    assert_eq!(csts.iter().map(Cst::text).collect::<String>(), text);
+}
+
+#[test]
+fn json_leaves() {
+    let text = " { \"a\" : [1, true] }\n";
+    let csts = json::parser().parse_lossless(text).unwrap();
+    lossless(text, &csts);
+    let leaves: Vec<_> = csts
+        .iter()
+        .flat_map(Cst::leaves)
+        .map(|leaf| match leaf {
+            Cst::Value(text, _) => format!("value({})", text),
+            Cst::Symbols(text, _) => format!("symbols({})", text),
+            Cst::Whitespace(text, _) => format!("whitespace({:?})", text),
+            Cst::Node { .. } => unreachable!(),
+        })
+        .collect();
+    assert_eq!(
+        leaves,
+        [
+            "whitespace(\" \")",
+            "symbols({)",
+            "whitespace(\" \")",
+            "symbols(\")",
+            "value(a)",
+            "symbols(\")",
+            "whitespace(\" \")",
+            "symbols(:)",
+            "whitespace(\" \")",
+            "symbols([)",
+            "value(1)",
+            "symbols(,)",
+            "whitespace(\" \")",
+            "symbols(true)",
+            "symbols(])",
+            "whitespace(\" \")",
+            "symbols(})",
+            "whitespace(\"\\n\")",
+        ]
+    );
+    match &csts[0] {
+        Cst::Node { kind, span, .. } => {
+            assert_eq!((kind.as_str(), span.clone()), (".object", 0..text.len()))
+        }
+        cst => panic!("{:?}", cst),
+    }
+}
+
+#[test]
+fn json_generated() {
+    let parser = json::parser();
+    let generator = json::generator();
+    for _ in 0..200 {
+        let text = generator.generate().unwrap();
+        lossless(&text, &parser.parse_lossless(&text).unwrap());
+    }
+}
+
+#[test]
+fn script_indentation() {
+    let text = "\nif a:\n    b\n\n    while c:\n\t    d\n    e\nf\n";
+    let csts = script::parser().parse_lossless(text).unwrap();
+    lossless(text, &csts);
+}
+
+#[test]
+fn arith_postfix() {
+    let parser = Parser::from(and(&"", arith::node()));
+    for text in ["-1", " 1 ", "1+2"] {
+        lossless(text, &parser.parse_lossless(text).unwrap());
+    }
+}
+
+#[test]
+fn nested_stores() {
+    let parser = Parser::from(and(&".x", syntax(".x", store(all!(store('a'), 'b')))));
+    let csts = parser.parse_lossless("ab").unwrap();
+    lossless("ab", &csts);
+    let parser = Parser::from(and(
+        &".x",
+        syntax(".x", all!(store(all!('a', store('b'))), store(""), 'c')),
+    ));
+    let csts = parser.parse_lossless("abc").unwrap();
+    lossless("abc", &csts);
+    let values: Vec<_> = csts
+        .iter()
+        .flat_map(Cst::leaves)
+        .filter_map(|leaf| match leaf {
+            Cst::Value(text, span) => Some((*text, span.clone())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(values, [("ab", 0..2), ("", 2..2)]);
+}
+
+#[test]
+fn invalid() {
+    assert!(json::parser().parse_lossless("[1,").is_err());
+}
@@ -3,6 +3,8 @@ use quint::json::*;
 use quint::node::*;
 use quint::parse::*;
 use quint::*;
+use std::sync::OnceLock;
+use std::thread;
 
 fn test(json: &str, syntax: Syntax) {
     assert_eq!(parse(json).unwrap(), syntax);
@@ -31,6 +33,14 @@ fn number() {
     test(r#"-0.1e2"#, Syntax::Number(-0.1e2));
 }
 
+#[test]
+fn unicode_string() {
+    test(
+        r#""héllo wörld ✓ \"ok\"""#,
+        Syntax::String(r#"héllo wörld ✓ \"ok\""#.into()),
+    );
+}
+
 #[test]
 fn null() {
     test(r#"null"#, Syntax::Null);
@@ -81,3 +91,72 @@ fn generate_mixed() {
         parser.parse(&text).first().unwrap();
     }
 }
+
+#[test]
+fn memoize() {
+    let parser = parser();
+    let memoized = parser.clone().memoize(1 << 16);
+    for text in [
+        r#"[0,[1,[2]]]"#,
+        r#"{ "a": [true, false, null], "b": { "c": -1.2E3 } }"#,
+        r#"[{}, [], "boba", { "fett": [{ "jango": 0 }] }]"#,
+    ] {
+        assert_eq!(
+            format!("{:?}", parser.parse(text)),
+            format!("{:?}", memoized.parse(text))
+        );
+    }
+}
+
+#[test]
+fn parse_parallel() {
+    static PARSER: OnceLock<Parser> = OnceLock::new();
+    let parser = PARSER.get_or_init(parser);
+    thread::scope(|scope| {
+        for index in 0..8 {
+            scope.spawn(move || {
+                let text = format!(r#"[{}, {{ "boba": [true, null] }}]"#, index);
+                assert_eq!(
+                    Syntax::Array(vec![
+                        Syntax::Number(index as f64),
+                        Syntax::Object(vec![(
+                            Syntax::String("boba".into()),
+                            Syntax::Array(vec![Syntax::Boolean(true), Syntax::Null])
+                        )])
+                    ]),
+                    convert(&parser.parse(&text)[0]).unwrap()
+                );
+            });
+        }
+    });
+}
+
+#[test]
+fn reduce_convert() {
+    let parser = parser();
+    let generator = generator();
+    for _ in 0..100 {
+        let text = generator.generate().unwrap();
+        let trees = parser.try_parse(&text).unwrap();
+        let syntaxes: Vec<_> = trees.iter().map(|tree| convert(tree).unwrap()).collect();
+        assert_eq!(parser.reduce(&text, &actions()).unwrap(), syntaxes);
+    }
+}
+
+#[test]
+fn reduce_object() {
+    let text = r#"{ "a": 1, "b": { "c": [true] }, "": {} }"#;
+    let string = |text: &str| Syntax::String(text.into());
+    let object = Syntax::Object(vec![
+        (string("a"), Syntax::Number(1.0)),
+        (
+            string("b"),
+            Syntax::Object(vec![(
+                string("c"),
+                Syntax::Array(vec![Syntax::Boolean(true)]),
+            )]),
+        ),
+        (string(""), Syntax::Object(Vec::new())),
+    ]);
+    assert_eq!(parser().reduce(text, &actions()).unwrap(), [object]);
+}
@@ -0,0 +1,416 @@
+extern crate quint;
+use quint::generate::*;
+use quint::machine::*;
+use quint::node::*;
+use quint::parse::*;
+use quint::*;
+
+fn test(node: Node, texts: &[&str]) {
+    let parser = Parser::from(node.clone());
+    let program = Program::from(node);
+    for text in texts {
+        assert_eq!(parser.recover(text), program.recover(text), "{}", text);
+    }
+}
+
+#[test]
+fn boba_fett() {
+    test(
+        all!("Boba", any!(' ', '_'), repeat(1.., any!("Fett", "Jango"))),
+        &[
+            "Boba Fett",
+            "Boba_JangoFett",
+            "Boba",
+            "Boba Karl",
+            "BobaFett",
+        ],
+    );
+}
+
+#[test]
+fn json() {
+    test(
+        and(&"", json::node()),
+        &[
+            "null",
+            "-1.2E3",
+            r#"[0, 1, "boba", { "fett": [true, false] }]"#,
+            r#"{ "boba": 1, "fett" }"#,
+            "[1, }",
+            "[1, \n \"boba\"]",
+        ],
+    );
+}
+
+#[test]
+fn error_rule() {
+    test(
+        and(&".boba", syntax(".boba", all!('(', option(&".boba"), ')'))),
+        &["((x", "(())", "(()"],
+    );
+}
+
+#[test]
+fn left_recursion() {
+    let digit = || store('0'..='9');
+    test(
+        all!(
+            &".add",
+            syntax(".add", any!(all!(&".add", '+', digit()), digit())),
+        ),
+        &["1+2+3", "1", "1+2+", "+"],
+    );
+    test(
+        all!(
+            &".boba",
+            syntax(".boba", any!(all!(&".fett", 'x'), 'a')),
+            syntax(".fett", any!(all!(&".boba", 'y'), 'b')),
+        ),
+        &["a", "bx", "ayx", "bxyxyx", "bxy"],
+    );
+    test(
+        all!(
+            &".expression",
+            define(".expression", any!(&".add", &".multiply", &".number")),
+            syntax(".add", all!(&".expression", '+', &".number")),
+            syntax(".multiply", all!(&".expression", '*', &".number")),
+            syntax(".number", store(repeat(1.., '0'..='9'))),
+        ),
+        &["1+22*3", "1*2+3*4", "1+"],
+    );
+}
+
+#[test]
+fn recover_statements() {
+    test(
+        all!(
+            &".block",
+            syntax(
+                ".block",
+                all!(
+                    '{',
+                    repeat(.., all!(recover(&".statement", any!(';', '}')), ';')),
+                    '}'
+                )
+            ),
+            syntax(".statement", store(any!("Boba", "Fett"))),
+        ),
+        &[
+            "{Boba;Jango;Fett;}",
+            "{Boba;Fett;}",
+            "{Boba;Fett;}}",
+            "{Jango",
+            "{Boba;Karl}",
+        ],
+    );
+    test(
+        all!(
+            repeat(.., recover(&".boba", ';')),
+            ';',
+            syntax(".boba", store("Boba")),
+        ),
+        &["BobaBoba;", "BobaFett;", "Fett", "Boba", ";"],
+    );
+}
+
+#[test]
+fn cut_statements() {
+    test(
+        all!(
+            &".block",
+            syntax(
+                ".block",
+                all!('{', repeat(.., all!(recover(&".statement", ';'), ';')), '}')
+            ),
+            syntax(
+                ".statement",
+                any!(
+                    all!("let ", cut(), store('a'..='z')),
+                    store(repeat(1.., any!('a'..='z', ' ')))
+                )
+            ),
+        ),
+        &["{let 1;Boba;let a;}", "{let a;let b}", "{let boba;}", "{}"],
+    );
+}
+
+#[test]
+fn cut_nested() {
+    test(
+        all!(
+            any!(
+                all!(any!(all!('a', cut(), 'b'), 'c'), 'd'),
+                store("ae"),
+                store("abe")
+            ),
+            any!(all!('x', cut(), 'y'), 'x')
+        ),
+        &["abdxy", "cdx", "aex", "abex", "acdx", "abexz"],
+    );
+    test(
+        all!(
+            define("commit", all!('a', cut(), 'b')),
+            any!(all!(any!(&"commit", 'c'), 'd'), store("ae"), store("abe"))
+        ),
+        &["abd", "cd", "ae", "abe", "acd"],
+    );
+}
+
+#[test]
+fn lookahead() {
+    let letter = || any!('a'..='z', '_');
+    test(
+        all!(
+            &".statement",
+            syntax(
+                ".statement",
+                any!(
+                    all!("if", not(letter()), ' ', &".statement"),
+                    all!(peek("el"), store(repeat(1.., letter())))
+                )
+            ),
+        ),
+        &["if else", "ifelse", "if_ else", "if boba", "else"],
+    );
+}
+
+#[test]
+fn class() {
+    test(
+        all!(
+            store(repeat(1.., any!('α'..='ω', one_of(['a'..='c', 'x'..='z'])))),
+            any!(all!('a'..='m', store('x')), all!('f'..='z', store('y'))),
+            none_of(['"'..='"'])
+        ),
+        &["αbxgy✓", "αby\"", "gx\"", "a"],
+    );
+}
+
+#[test]
+fn dump() {
+    let program = Program::from(and(&".boba", syntax(".boba", any!("Boba", "Fett"))));
+    assert!(program
+        .instructions()
+        .contains(&Instruction::Text("ett".into())));
+    let dump = program.to_string();
+    assert!(dump.starts_with("root:"));
+    assert!(dump.contains("switch {'B': 1, 'F': 7}"));
+    assert!(dump.contains(r#"text "oba""#));
+}
+
+#[test]
+fn indentation() {
+    test(
+        all!(
+            &".item",
+            syntax(
+                ".item",
+                all!(
+                    store('a'..='z'),
+                    option(all!(
+                        ":\n",
+                        indent(),
+                        join(all!('\n', same_indent()), &".item"),
+                        dedent()
+                    ))
+                )
+            ),
+        ),
+        &[
+            "a:\n b\n c",
+            "a:\n b:\n   c\n d",
+            "a:\nb",
+            "a:\n  b\n c",
+            "a:\n b:\n  c\n",
+        ],
+    );
+}
+
+#[test]
+fn state() {
+    let counted = |symbol, slot| repeat(.., all!(symbol, set(slot, Set::Add(1))));
+    test(
+        all!(
+            counted('a', ".a"),
+            counted('b', ".b"),
+            any!(
+                all!(check(".a", If::Greater, ".b"), store('>')),
+                all!(check(".a", If::LessEqual, ".b"), store('<'))
+            ),
+            scope(
+                &[".a"],
+                all!(
+                    set(".a", Set::Multiply(2)),
+                    check(".a", If::GreaterEqual, ".b")
+                )
+            ),
+            check(".a", If::NotEqual, ".c"),
+        ),
+        &["aab>", "abb<", "abbb<", "", "ab>"],
+    );
+}
+
+#[test]
+fn back_references() {
+    test(
+        all!(
+            &".element",
+            syntax(
+                ".element",
+                all!(
+                    '<',
+                    capture("tag", repeat(1.., 'a'..='z')),
+                    '>',
+                    repeat(.., any!(&".element", store(repeat(1.., 'A'..='Z')))),
+                    "</",
+                    back("tag"),
+                    '>'
+                )
+            ),
+        ),
+        &[
+            "<a><b>X</b>Y<c></c></a>",
+            "<a><b></a></b>",
+            "<ab></a>",
+            "<a></ab>",
+        ],
+    );
+}
+
+#[test]
+fn counted_repetition() {
+    test(
+        all!(integer(".n", 10), ':', repeat_value(".n", 'a'), ','),
+        &["3:aaa,", "0:,", "3:aa,", "3:aaaa,", ":a,", "10:aaaaaaaaaa,"],
+    );
+    let text = format!("100000:{},", "a".repeat(100000));
+    test(
+        all!(integer(".n", 10), ':', repeat_value(".n", 'a'), ','),
+        &[&text, "99999999999999999999:a,"],
+    );
+}
+
+#[test]
+fn native() {
+    let digits = quint::node::native(&[".n"], |text, values| {
+        let length = text
+            .find(|symbol: char| !symbol.is_ascii_digit())
+            .unwrap_or(text.len());
+        (length as isize == values[0]).then_some(length)
+    });
+    test(
+        all!(integer(".n", 10), ':', digits, ';'),
+        &["3:123;", "2:123;", "0:;", "1:;", "1:é;"],
+    );
+}
+
+#[test]
+fn fields() {
+    let number = syntax(".number", store_as("digits", repeat(1.., '0'..='9')));
+    let node = all!(
+        &".sum",
+        syntax(
+            ".sum",
+            join('+', field("term", any!(&".number", &".group")))
+        ),
+        syntax(".group", all!('(', field("inner", &".sum"), ')')),
+        number,
+    );
+    test(node, &["1+(2+3)", "(1)+((2))", "1+", "(1+2", "12+3"]);
+    let node = all!(
+        &".pair",
+        syntax(".pair", all!(&".x", field("second", option(&".x")))),
+        syntax(".x", 'x'),
+    );
+    test(node, &["x", "xx", "xxx"]);
+}
+
+#[test]
+fn undo() {
+    test(
+        all!(
+            any!(
+                all!(
+                    scope(&[".a"], set(".a", Set::Value(1))),
+                    check(".a", If::NotEqual, ".b")
+                ),
+                all!(scope(&[".a"], set(".a", Set::Value(2))), 'y')
+            ),
+            check(".a", If::Equal, ".b")
+        ),
+        &["y", "x", ""],
+    );
+    test(
+        all!(
+            any!(all!(&".boba", check(".a", If::NotEqual, ".b")), &".fett"),
+            syntax(".boba", capture("name", 'a')),
+            syntax(".fett", all!(capture("name", 'a'), store(back("name")))),
+        ),
+        &["aa", "ab", "a"],
+    );
+}
+
+#[test]
+fn precedence() {
+    test(
+        and(&"", arith::node()),
+        &["1+2*3", "-(1+2)*3", "1++--", "1++2", "(1", "1+*2", ""],
+    );
+    test(
+        all!(
+            &".",
+            define(".", precede(&"pre", &"post")),
+            syntax("pre.number", prefix(100, store('0'..='9'))),
+            syntax("post.add", postfix(10, Bind::Left, all!('+', &"."))),
+            syntax("post.power", postfix(30, Bind::Right, all!('^', &"."))),
+        ),
+        &["1+2+3", "1^2^3", "1+2^3", "1^", ""],
+    );
+}
+
+#[test]
+fn script() {
+    test(
+        script::node(),
+        &[
+            "if a:\n    b\n    while c:\n        d\n    e\nf\n",
+            "if a:\nb",
+            "while a:\n  b\n c",
+        ],
+    );
+}
+
+#[test]
+fn generated() {
+    let nodes = [and(&"", json::node()), script::node()];
+    for node in nodes {
+        let generator = Generator::from(node.clone());
+        let parser = Parser::from(node.clone());
+        let program = Program::from(node);
+        for _ in 0..32 {
+            if let Some(text) = generator.generate() {
+                // Every prefix of a valid text is compared such that errors are covered as well.
+                for (index, _) in text.char_indices().step_by(7) {
+                    let text = &text[..index];
+                    assert_eq!(parser.recover(text), program.recover(text), "{}", text);
+                }
+                assert_eq!(parser.recover(&text), program.recover(&text), "{}", text);
+            }
+        }
+    }
+}
+
+#[test]
+fn memoize() {
+    // A 'Program' does not memoize, which must not change its results.
+    let node = all!(
+        &".add",
+        syntax(".add", any!(all!(&".add", '+', &".term"), &".term")),
+        syntax(".term", any!(all!('(', &".add", ')'), store('0'..='9'))),
+    );
+    let parser = Parser::from(node.clone()).memoize(1024);
+    let program = Program::from(node);
+    for text in ["1+(2+3)+4", "(((1)))", "1+(2+", "1+)"] {
+        assert_eq!(parser.recover(text), program.recover(text), "{}", text);
+    }
+}
@@ -0,0 +1,149 @@
+extern crate quint;
+use quint::json;
+use quint::node::*;
+use quint::parse::*;
+use quint::query::*;
+use quint::*;
+
+const TEXT: &str = r#"{ "a": [1, "b"], "c": { "d": "e" } }"#;
+
+fn kinds<'a>(trees: impl IntoIterator<Item = &'a Tree<'a>>) -> Vec<String> {
+    trees
+        .into_iter()
+        .map(|tree| match tree.values.first() {
+            Some(value) => format!("{}({})", tree.kind, value),
+            None => tree.kind.clone(),
+        })
+        .collect()
+}
+
+#[test]
+fn orders() {
+    let trees = json::parser().try_parse(TEXT).unwrap();
+    assert_eq!(
+        kinds(trees[0].pre_order()),
+        [
+            ".object",
+            ".string(a)",
+            ".array",
+            ".number(1)",
+            ".string(b)",
+            ".string(c)",
+            ".object",
+            ".string(d)",
+            ".string(e)"
+        ]
+    );
+    assert_eq!(
+        kinds(trees[0].post_order()),
+        [
+            ".string(a)",
+            ".number(1)",
+            ".string(b)",
+            ".array",
+            ".string(c)",
+            ".string(d)",
+            ".string(e)",
+            ".object",
+            ".object"
+        ]
+    );
+}
+
+#[test]
+fn families() {
+    assert!(member(".b", ".b"));
+    assert!(member(".b", ".b.0"));
+    assert!(member(".b", ".b.0.1"));
+    assert!(!member(".b", ".bc"));
+    assert!(!member(".b.0", ".b"));
+    assert!(member("*", ".b"));
+
+    let trees = Parser::from(all!(
+        repeat(1.., any!(&".b", &".c")),
+        syntax(".b.0", store('0')),
+        syntax(".b.1", store('1')),
+        syntax(".c", all!('(', repeat(.., &".b"), ')')),
+    ))
+    .try_parse("01(1)0")
+    .unwrap();
+    let found: Vec<_> = trees.iter().flat_map(|tree| tree.find_all(".b")).collect();
+    assert_eq!(kinds(found), [".b.0(0)", ".b.1(1)", ".b.1(1)", ".b.0(0)"]);
+    assert_eq!(kinds(trees[2].find(".b")), [".b.1(1)"]);
+    assert!(trees[0].find(".c").is_none());
+}
+
+#[test]
+fn find() {
+    let trees = json::parser().try_parse(TEXT).unwrap();
+    assert_eq!(kinds(trees[0].find(".number")), [".number(1)"]);
+    assert_eq!(kinds(trees[0].find(".object")), [".object"]);
+    assert!(trees[0].find(".null").is_none());
+}
+
+#[test]
+fn select() {
+    let trees = json::parser().try_parse(TEXT).unwrap();
+    let tree = &trees[0];
+    assert_eq!(
+        kinds(tree.select(".object > .string").unwrap()),
+        [".string(a)", ".string(c)", ".string(d)", ".string(e)"]
+    );
+    assert_eq!(
+        kinds(tree.select(".array > .string").unwrap()),
+        [".string(b)"]
+    );
+    assert_eq!(kinds(tree.select(".object .string").unwrap()).len(), 5);
+    assert_eq!(
+        kinds(tree.select(".object>.object>.string").unwrap()),
+        [".string(d)", ".string(e)"]
+    );
+    assert_eq!(
+        kinds(tree.select(".object .object > *").unwrap()),
+        [".string(d)", ".string(e)"]
+    );
+    assert_eq!(kinds(tree.select(".array > .array").unwrap()).len(), 0);
+}
+
+#[test]
+fn select_invalid() {
+    let trees = json::parser().try_parse(TEXT).unwrap();
+    for (selector, index) in [
+        ("", 0),
+        ("  ", 2),
+        (".object >", 9),
+        ("> .a", 0),
+        (".a > > .b", 5),
+    ] {
+        let error = trees[0].select(selector).unwrap_err();
+        assert_eq!(error.index, index, "{}", selector);
+        assert_eq!(error.expected, [Expect::Rule("family".into())]);
+    }
+    assert_eq!(
+        trees[0].select(".a >> .b").unwrap_err().to_string(),
+        "1:5: expected family"
+    );
+}
+
+#[test]
+fn cursor() {
+    let trees = json::parser().try_parse(TEXT).unwrap();
+    let mut cursor = trees[0].cursor();
+    assert!(!cursor.parent());
+    assert!(!cursor.next_sibling());
+    assert!(cursor.child(1));
+    assert_eq!(cursor.tree().kind, ".array");
+    assert!(cursor.last_child());
+    assert_eq!(cursor.tree().values, ["b"]);
+    assert_eq!((cursor.depth(), cursor.index()), (2, Some(1)));
+    assert!(!cursor.next_sibling());
+    assert!(cursor.previous_sibling());
+    assert_eq!(cursor.tree().values, ["1"]);
+    assert!(!cursor.first_child());
+    assert!(cursor.parent());
+    assert!(cursor.next_sibling());
+    assert_eq!(cursor.tree().values, ["c"]);
+    assert!(cursor.parent());
+    assert_eq!(cursor.tree().kind, ".object");
+    assert_eq!(cursor.index(), None);
+}
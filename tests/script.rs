@@ -0,0 +1,94 @@
+extern crate quint;
+use quint::parse::*;
+use quint::script::*;
+use std::sync::OnceLock;
+
+fn cached() -> &'static Parser {
+    static PARSER: OnceLock<Parser> = OnceLock::new();
+    PARSER.get_or_init(parser)
+}
+
+fn parse(text: &str) -> Option<Vec<Syntax>> {
+    let trees = cached().try_parse(text).ok()?;
+    trees.iter().map(convert).collect()
+}
+
+fn test(text: &str, syntax: Vec<Syntax>) {
+    assert_eq!(syntax, parse(text).unwrap());
+}
+
+fn name(name: &str) -> Syntax {
+    Syntax::Name(name.into())
+}
+
+#[test]
+fn statements() {
+    test("boba\nfett", vec![name("boba"), name("fett")]);
+    test("\n\nboba\n  \nfett\n", vec![name("boba"), name("fett")]);
+    test("", vec![]);
+}
+
+#[test]
+fn block() {
+    test(
+        "if boba:\n    fett\n    jango\nkarl",
+        vec![
+            Syntax::If("boba".into(), vec![name("fett"), name("jango")]),
+            name("karl"),
+        ],
+    );
+}
+
+#[test]
+fn nested_blocks() {
+    let text = "while boba:\n  if fett:\n     jango\n\n     karl\n  solo\nhan\n";
+    test(
+        text,
+        vec![
+            Syntax::While(
+                "boba".into(),
+                vec![
+                    Syntax::If("fett".into(), vec![name("jango"), name("karl")]),
+                    name("solo"),
+                ],
+            ),
+            name("han"),
+        ],
+    );
+}
+
+#[test]
+fn dedent_many() {
+    test(
+        "if a:\n if b:\n  if c:\n   d\ne",
+        vec![
+            Syntax::If(
+                "a".into(),
+                vec![Syntax::If(
+                    "b".into(),
+                    vec![Syntax::If("c".into(), vec![name("d")])],
+                )],
+            ),
+            name("e"),
+        ],
+    );
+}
+
+#[test]
+fn invalid_indentation() {
+    // Not deeper than the enclosing block.
+    assert!(parse("if boba:\nfett").is_none());
+    // Deeper without a block.
+    assert!(parse("boba\n  fett").is_none());
+    // Not matching any enclosing level.
+    assert!(parse("if boba:\n    fett\n  jango").is_none());
+    assert!(parse("  boba").is_none());
+}
+
+#[test]
+fn error_index() {
+    let error = cached()
+        .try_parse("if boba:\n    fett\n  jango")
+        .unwrap_err();
+    assert_eq!((3, 3), (error.line, error.column));
+}
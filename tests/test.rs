@@ -1,10 +1,12 @@
 extern crate quint;
+use quint::generate::*;
 use quint::node::*;
 use quint::parse::*;
 use quint::*;
+use std::thread;
 
 fn test(text: &str, node: Node, success: bool) {
-    assert_eq!(success, Parser::from(node).parse(text).first().is_some());
+    assert_eq!(success, Parser::from(node).try_parse(text).is_ok());
 }
 
 #[test]
@@ -89,7 +91,7 @@ fn join_boba() {
 
 #[test]
 fn spawn_boba() {
-    let parser = Parser::from(and(&"Boba", define("Boba", store("Fett"))));
+    let parser = Parser::from(and(&"Boba", syntax("Boba", store("Fett"))));
     let tree = &parser.parse("Fett")[0];
     assert_eq!("Boba", tree.kind);
     assert_eq!("Fett", tree.values[0]);
@@ -106,3 +108,1255 @@ fn refer_boba_fett() {
     ]);
     test("Boba Fett", node.clone(), true);
 }
+
+#[test]
+fn error_boba_fett() {
+    let parser = Parser::from(all!("Boba", ' ', any!("Fett", "Jango")));
+    let error = parser.try_parse("Boba Solo").unwrap_err();
+    assert_eq!(5, error.index);
+    assert_eq!((1, 6), (error.line, error.column));
+    assert!(error.expected.contains(&Expect::Symbol('F')));
+    assert!(error.expected.contains(&Expect::Symbol('J')));
+}
+
+#[test]
+fn error_furthest_line() {
+    let parser = Parser::from(and(
+        &".boba",
+        syntax(".boba", all!("Boba", '\n', any!("Fett", "Fetch"))),
+    ));
+    let error = parser.try_parse("Boba\nFetx").unwrap_err();
+    assert_eq!(8, error.index);
+    assert_eq!((2, 4), (error.line, error.column));
+    assert_eq!(
+        vec![Expect::Symbol('c'), Expect::Symbol('t')],
+        error.expected
+    );
+    assert_eq!("2:4: expected 'c', 't'", error.to_string());
+}
+
+#[test]
+fn error_json() {
+    let parser = quint::json::parser();
+    let error = parser.try_parse("[1, }").unwrap_err();
+    assert_eq!(4, error.index);
+    assert!(error.expected.contains(&Expect::Symbol('[')));
+    assert!(error.expected.contains(&Expect::Symbol('"')));
+    assert!(!error.expected.contains(&Expect::Symbol(']')));
+}
+
+#[test]
+fn error_rule() {
+    let parser = Parser::from(and(
+        &".boba",
+        syntax(".boba", all!('(', option(&".boba"), ')')),
+    ));
+    let error = parser.try_parse("((x").unwrap_err();
+    assert_eq!(2, error.index);
+    assert!(error.expected.contains(&Expect::Rule(".boba".into())));
+}
+
+#[test]
+fn error_trailing() {
+    let parser = Parser::from(and(&".boba", syntax(".boba", "Boba")));
+    assert!(parser.try_parse("Boba").is_ok());
+    let error = parser.try_parse("BobaFett").unwrap_err();
+    assert_eq!(4, error.index);
+}
+
+#[test]
+fn span_boba_fett() {
+    let parser = Parser::from(all!(
+        "Boba ",
+        &".fett",
+        syntax(".fett", all!("Fe", store("tt"), repeat(.., &".jango"))),
+        syntax(".jango", all!(' ', store("Jango"))),
+    ));
+    let trees = parser.parse("Boba Fett Jango Jango");
+    let fett = &trees[0];
+    assert_eq!(5..21, fett.span);
+    assert_eq!(vec![7..9], fett.spans);
+    assert_eq!(9..15, fett.children[0].span);
+    assert_eq!(vec![10..15], fett.children[0].spans);
+    assert_eq!(15..21, fett.children[1].span);
+    assert_eq!(vec![16..21], fett.children[1].spans);
+}
+
+#[test]
+fn span_json() {
+    let text = "[1, \n \"boba\"]";
+    let trees = quint::json::parser().parse(text);
+    let array = &trees[0];
+    assert_eq!(0..text.len(), array.span);
+    assert_eq!(1..2, array.children[0].spans[0]);
+    assert_eq!(7..11, array.children[1].spans[0]);
+    assert_eq!("boba", &text[array.children[1].spans[0].clone()]);
+    assert_eq!((2, 3), position(text, array.children[1].spans[0].start));
+}
+
+#[test]
+fn memoize_boba() {
+    let node = all!(
+        &".boba",
+        syntax(
+            ".boba",
+            any!(
+                all!('(', store(&".boba"), ')', 'x'),
+                all!('(', &".boba", ')', 'y'),
+                "Boba",
+            )
+        ),
+    );
+    let text = "((((Boba)x)y)y)x";
+    let expected = format!("{:?}", Parser::from(node.clone()).parse(text));
+    for capacity in [0, 1, 2, 1024] {
+        let trees = Parser::from(node.clone()).memoize(capacity).parse(text);
+        assert_eq!(expected, format!("{:?}", trees));
+    }
+    assert!(Parser::from(node)
+        .memoize(1024)
+        .try_parse("((Boba)z)")
+        .is_err());
+}
+
+#[test]
+fn memoize_errors() {
+    // The failure of '.boba' under 'not' is memoized while its expectations are discarded.
+    let node = all!(
+        any!(all!(not(&".boba"), "Fett"), &".boba"),
+        syntax(".boba", all!('(', repeat(.., 'a'..='z'), ')')),
+    );
+    for text in ["(boba", "(boba]", "[boba)"] {
+        let error = Parser::from(node.clone()).try_parse(text).unwrap_err();
+        let memoized = Parser::from(node.clone()).memoize(1024).try_parse(text);
+        assert_eq!(Err(error), memoized);
+    }
+}
+
+#[test]
+fn left_recursion_direct() {
+    let digit = || store('0'..='9');
+    let parser = Parser::from(all!(
+        &".add",
+        syntax(".add", any!(all!(&".add", '+', digit()), digit())),
+    ));
+    let trees = parser.parse("1+2+3");
+    assert_eq!(
+        r#"[.add("3"): { .add("2"): { .add("1") } }]"#,
+        format!("{:?}", trees)
+    );
+    assert_eq!(0..5, trees[0].span);
+    assert_eq!(0..3, trees[0].children[0].span);
+    assert!(parser.try_parse("1+2+").is_err());
+    assert!(parser.memoize(1024).try_parse("1+2+3").is_ok());
+}
+
+#[test]
+fn left_recursion_indirect() {
+    let parser = Parser::from(all!(
+        &".boba",
+        syntax(".boba", any!(all!(&".fett", 'x'), 'a')),
+        syntax(".fett", any!(all!(&".boba", 'y'), 'b')),
+    ));
+    assert!(parser.try_parse("a").is_ok());
+    assert!(parser.try_parse("bx").is_ok());
+    assert!(parser.try_parse("ayx").is_ok());
+    assert!(parser.try_parse("bxyxyx").is_ok());
+    assert!(parser.try_parse("bxy").is_err());
+    let trees = parser.parse("ayx");
+    assert_eq!(".boba: { .fett: { .boba } }", format!("{}", trees[0]));
+}
+
+#[test]
+fn left_recursion_expression() {
+    let parser = Parser::from(all!(
+        &".expression",
+        define(".expression", any!(&".add", &".multiply", &".number")),
+        syntax(".add", all!(&".expression", '+', &".number")),
+        syntax(".multiply", all!(&".expression", '*', &".number")),
+        syntax(".number", store(repeat(1.., '0'..='9'))),
+    ));
+    let trees = parser.parse("1+22*3");
+    assert_eq!(
+        r#"[.multiply: { .add: { .number("1"), .number("22") }, .number("3") }]"#,
+        format!("{:?}", trees)
+    );
+}
+
+#[test]
+fn recover_statements() {
+    let parser = Parser::from(all!(
+        &".block",
+        syntax(
+            ".block",
+            all!(
+                '{',
+                repeat(.., all!(recover(&".statement", any!(';', '}')), ';')),
+                '}'
+            )
+        ),
+        syntax(".statement", store(any!("Boba", "Fett"))),
+    ));
+    let (trees, diagnostics) = parser.recover("{Boba;Jango;Fett;}");
+    assert_eq!(
+        r#"[.block: { .statement("Boba"), error("Jango"), .statement("Fett") }]"#,
+        format!("{:?}", trees)
+    );
+    assert_eq!(6..11, trees[0].children[1].span);
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(6, diagnostics[0].index);
+    assert_eq!(
+        vec![
+            Expect::Symbol('B'),
+            Expect::Symbol('F'),
+            Expect::Rule(".statement".into())
+        ],
+        diagnostics[0].expected
+    );
+    assert!(parser.try_parse("{Boba;Jango;Fett;}").is_err());
+    assert!(parser.try_parse("{Boba;Fett;}").is_ok());
+
+    let (trees, diagnostics) = parser.recover("{Boba;Fett;}}");
+    assert_eq!(1, trees.len());
+    assert_eq!(12, diagnostics[0].index);
+}
+
+#[test]
+fn recover_progress() {
+    let parser = Parser::from(all!(
+        repeat(.., recover(&".boba", ';')),
+        ';',
+        syntax(".boba", store("Boba")),
+    ));
+    let (trees, diagnostics) = parser.recover("BobaBoba;");
+    assert_eq!(r#"[.boba("Boba"), .boba("Boba")]"#, format!("{:?}", trees));
+    assert!(diagnostics.is_empty());
+    let (trees, diagnostics) = parser.recover("BobaFett;");
+    assert_eq!(r#"[.boba("Boba"), error("Fett")]"#, format!("{:?}", trees));
+    assert_eq!(1, diagnostics.len());
+    assert!(parser.try_parse("Boba").is_err());
+
+    let parser = Parser::from(all!(
+        recover(&".boba", ';'),
+        ';',
+        syntax(".boba", store("Boba")),
+    ));
+    let (trees, diagnostics) = parser.recover(";");
+    assert!(trees.is_empty());
+    assert_eq!(1, diagnostics.len());
+}
+
+#[test]
+fn cut_keyword() {
+    let word = || store(repeat(1.., 'a'..='z'));
+    let node = |cut: Node| {
+        all!(
+            &".statement",
+            syntax(
+                ".statement",
+                any!(
+                    all!("class ", cut, word()),
+                    store(repeat(1.., any!('a'..='z', ' ', '0'..='9')))
+                )
+            ),
+        )
+    };
+    let parser = Parser::from(node(true.node()));
+    assert!(parser.try_parse("class boba").is_ok());
+    assert!(parser.try_parse("class 123").is_ok());
+
+    let parser = Parser::from(node(cut()));
+    assert!(parser.try_parse("class boba").is_ok());
+    assert!(parser.try_parse("boba fett").is_ok());
+    let error = parser.try_parse("class 123").unwrap_err();
+    assert_eq!(6, error.index);
+    assert!(error.expected.contains(&Expect::Range('a', 'z')));
+    assert!(!error.expected.contains(&Expect::Symbol('1')));
+    assert!(parser.memoize(1024).try_parse("class 123").is_err());
+}
+
+#[test]
+fn cut_recover() {
+    let parser = Parser::from(all!(
+        &".block",
+        syntax(
+            ".block",
+            all!('{', repeat(.., all!(recover(&".statement", ';'), ';')), '}')
+        ),
+        syntax(
+            ".statement",
+            any!(all!("let ", cut(), store('a'..='z')), store("Boba"))
+        ),
+    ));
+    let (trees, diagnostics) = parser.recover("{let 1;Boba;let a;}");
+    assert_eq!(
+        r#"[.block: { error("let 1"), .statement("Boba"), .statement("a") }]"#,
+        format!("{:?}", trees)
+    );
+    assert_eq!(1, diagnostics.len());
+    assert_eq!(5, diagnostics[0].index);
+}
+
+#[test]
+fn cut_nested() {
+    // A cut commits the innermost 'Or' that encloses it, whether it fails or succeeds.
+    let parser = Parser::from(all!(
+        &".boba",
+        syntax(
+            ".boba",
+            any!(
+                all!(any!(all!('a', cut(), 'b'), 'c'), 'd'),
+                store("ae"),
+                store("abe")
+            )
+        ),
+    ));
+    for text in ["abd", "cd", "ae", "abe"] {
+        assert!(parser.try_parse(text).is_ok(), "{}", text);
+    }
+    assert!(parser.try_parse("acd").is_err());
+    assert!(parser.memoize(1024).try_parse("abe").is_ok());
+}
+
+#[test]
+fn cut_rule() {
+    // A cut in a rule commits the innermost 'Or' that encloses the reference to the rule.
+    let parser = Parser::from(all!(
+        &".boba",
+        define("commit", all!('a', cut(), 'b')),
+        syntax(
+            ".boba",
+            any!(all!(any!(&"commit", 'c'), 'd'), store("ae"), store("abe"))
+        ),
+    ));
+    for text in ["abd", "cd", "ae", "abe"] {
+        assert!(parser.try_parse(text).is_ok(), "{}", text);
+    }
+    assert!(parser.try_parse("acd").is_err());
+    assert!(parser.memoize(1024).try_parse("abe").is_ok());
+}
+
+#[test]
+fn not_keyword() {
+    let letter = || any!('a'..='z', '_');
+    let parser = Parser::from(all!(
+        &".statement",
+        syntax(
+            ".statement",
+            any!(
+                all!("if", not(letter()), ' ', &".statement"),
+                store(repeat(1.., letter()))
+            )
+        ),
+    ));
+    assert_eq!(
+        r#"[.statement: { .statement("boba") }]"#,
+        format!("{:?}", parser.parse("if boba"))
+    );
+    assert_eq!(
+        r#"[.statement("iffett")]"#,
+        format!("{:?}", parser.parse("iffett"))
+    );
+    assert!(parser.try_parse("if_ boba").is_err());
+}
+
+#[test]
+fn not_until() {
+    let parser = Parser::from(all!(
+        "/*",
+        store(repeat(.., all!(not("*/"), any!('a'..='z', ' ', '*', '/')))),
+        "*/",
+        &".boba",
+        syntax(".boba", "Boba")
+    ));
+    let trees = parser.parse("/* boba * fett / */Boba");
+    assert_eq!(1, trees.len());
+    assert!(parser.try_parse("/* boba */ fett */Boba").is_err());
+}
+
+#[test]
+fn peek_boba() {
+    let parser = Parser::from(all!(
+        peek(all!("Boba", ' ')),
+        &".boba",
+        syntax(".boba", store(repeat(1.., any!('a'..='z', 'A'..='Z', ' '))))
+    ));
+    assert_eq!(
+        r#"[.boba("Boba Fett")]"#,
+        format!("{:?}", parser.parse("Boba Fett"))
+    );
+    let error = parser.try_parse("Fett Boba").unwrap_err();
+    assert_eq!(0, error.index);
+    assert!(parser.try_parse("Boba").is_err());
+}
+
+#[test]
+fn generate_not() {
+    let node = all!(
+        repeat(1..4, any!('a', 'b')),
+        not(all!('a', 'a')),
+        any!('a', 'c')
+    );
+    let generator = Generator::from(node.clone());
+    let parser = Parser::from(node);
+    for _ in 0..64 {
+        let text = generator.generate().unwrap();
+        assert!(parser.try_parse(&text).is_ok(), "{}", text);
+    }
+}
+
+#[test]
+fn class_unicode() {
+    let parser = Parser::from(and(
+        &".greek",
+        syntax(".greek", store(repeat(1.., 'α'..='ω'))),
+    ));
+    assert_eq!(r#"[.greek("αβγ")]"#, format!("{:?}", parser.parse("αβγ")));
+    let error = parser.try_parse("αb").unwrap_err();
+    assert_eq!(2, error.index);
+    assert_eq!(vec![Expect::Range('α', 'ω')], error.expected);
+
+    let parser = Parser::from(repeat(1.., '\u{0}'..='\u{10FFFF}'));
+    assert!(parser.try_parse("a\u{0}é\u{FFFF}\u{10FFFF}").is_ok());
+}
+
+#[test]
+fn class_none_of() {
+    let parser = Parser::from(and(
+        &".string",
+        syntax(
+            ".string",
+            all!(
+                '"',
+                store(repeat(.., none_of(['"'..='"', '\\'..='\\']))),
+                '"'
+            ),
+        ),
+    ));
+    assert_eq!(
+        r#"[.string("boba ✓ fett")]"#,
+        format!("{:?}", parser.parse(r#""boba ✓ fett""#))
+    );
+    assert!(parser.try_parse(r#""bo\ba""#).is_err());
+}
+
+#[test]
+fn class_buckets() {
+    let parser = Parser::from(and(
+        &".boba",
+        syntax(
+            ".boba",
+            any!(
+                all!('a'..='m', store('x')),
+                all!('f'..='z', store('y')),
+                all!('h', store('z'))
+            ),
+        ),
+    ));
+    assert_eq!(r#"[.boba("x")]"#, format!("{:?}", parser.parse("bx")));
+    assert_eq!(r#"[.boba("x")]"#, format!("{:?}", parser.parse("gx")));
+    assert_eq!(r#"[.boba("y")]"#, format!("{:?}", parser.parse("gy")));
+    assert_eq!(r#"[.boba("z")]"#, format!("{:?}", parser.parse("hz")));
+    assert!(parser.try_parse("by").is_err());
+    assert!(parser.try_parse("nx").is_err());
+}
+
+#[test]
+fn class_ranges() {
+    assert_eq!(
+        vec!['a'..='f', 'x'..='z'],
+        class::merge(['d'..='f', 'x'..='z', 'a'..='c', 'b'..='e'])
+    );
+    assert_eq!(
+        vec!['\u{0}'..='`', 'g'..='w', '{'..=char::MAX],
+        class::complement(&['a'..='f', 'x'..='z'])
+    );
+    assert_eq!(
+        vec!['\u{0}'..='\u{D7FF}'],
+        class::complement(&['\u{E000}'..=char::MAX])
+    );
+    assert_eq!(Some(1), class::search(&['a'..='f', 'x'..='z'], 'y'));
+    assert_eq!(None, class::search(&['a'..='f', 'x'..='z'], 'g'));
+}
+
+#[test]
+fn class_identifier() {
+    let parser = Parser::from(and(
+        &".identifier",
+        syntax(
+            ".identifier",
+            store(all!(
+                any!(class::xid_start(), '_'),
+                repeat(.., class::xid_continue())
+            )),
+        ),
+    ));
+    for text in ["boba", "größe_1", "π", "_fett", "东京"] {
+        assert_eq!(vec![text], parser.parse(text)[0].values);
+    }
+    assert!(parser.try_parse("1boba").is_err());
+    assert!(parser.try_parse("bo-ba").is_err());
+    assert!(parser.try_parse("·boba").is_err());
+}
+
+#[test]
+fn class_properties() {
+    let parser = Parser::from(all!(
+        repeat(1.., class::alphabetic()),
+        repeat(1.., class::white_space()),
+        repeat(1.., class::alphabetic())
+    ));
+    assert!(parser.try_parse("Ωmega\u{3000}\u{a0}\tπ").is_ok());
+    assert!(parser.try_parse("boba 1").is_err());
+    assert!(parser.try_parse("boba_fett").is_err());
+}
+
+#[test]
+fn class_spot_checks() {
+    // Members and non-members of each property as listed by the Unicode Character Database 14.0.0.
+    let properties = [
+        (
+            class::xid_start(),
+            &['A', 'ª', 'Ⅰ', '𐐀'][..],
+            &['0', '_', '·', '\u{300}', '\u{2e2f}', '\u{309b}'][..],
+        ),
+        (
+            class::xid_continue(),
+            &['A', '0', '_', '·', '\u{300}', '\u{345}'],
+            &['-', '\u{2e2f}', '\u{309b}', '\u{200b}', '😀'],
+        ),
+        (
+            class::alphabetic(),
+            &['A', 'ª', '\u{345}', '\u{2e2f}', 'Ⅰ', '𐐀'],
+            &['1', '_', '·', '\u{300}', '😀'],
+        ),
+        (
+            class::white_space(),
+            &[' ', '\t', '\u{85}', '\u{a0}', '\u{3000}'],
+            &['A', '\u{180e}', '\u{200b}'],
+        ),
+    ];
+    for (node, members, others) in properties {
+        let parser = Parser::from(node);
+        for member in members {
+            assert!(
+                parser.try_parse(&member.to_string()).is_ok(),
+                "{:?}",
+                member
+            );
+        }
+        for other in others {
+            assert!(parser.try_parse(&other.to_string()).is_err(), "{:?}", other);
+        }
+    }
+}
+
+#[test]
+#[ignore = "requires perl with its Unicode::UCD module"]
+fn class_table() {
+    let root = env!("CARGO_MANIFEST_DIR");
+    let output = std::process::Command::new("perl")
+        .arg(format!("{}/tools/ucd.pl", root))
+        .output()
+        .unwrap();
+    let table = std::fs::read_to_string(format!("{}/src/class/table.rs", root)).unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), table);
+}
+
+#[test]
+fn generate_class() {
+    let node = all!(repeat(
+        1..8,
+        any!('α'..='ω', none_of(['\u{0}'..='\u{FFFF}']))
+    ));
+    let generator = Generator::from(node.clone());
+    let parser = Parser::from(node);
+    for _ in 0..64 {
+        let text = generator.generate().unwrap();
+        assert!(parser.try_parse(&text).is_ok(), "{}", text);
+    }
+}
+
+#[test]
+fn generate_properties() {
+    let node = all!(
+        class::xid_start(),
+        repeat(.., class::xid_continue()),
+        class::white_space(),
+        repeat(1..8, class::alphabetic())
+    );
+    let generator = Generator::from(node.clone());
+    let parser = Parser::from(node);
+    for _ in 0..64 {
+        let text = generator.generate().unwrap();
+        assert!(parser.try_parse(&text).is_ok(), "{}", text);
+    }
+}
+
+#[test]
+fn parse_all_boba() {
+    let parser = Parser::from(all!(
+        repeat(1.., any!(&".one", &".two")),
+        syntax(".one", 'a'),
+        syntax(".two", "aa"),
+    ));
+    let parses: Vec<_> = parser
+        .parse_all("aaa")
+        .map(|trees| format!("{:?}", trees))
+        .collect();
+    assert_eq!(3, parses.len());
+    assert!(parses.contains(&"[.one, .one, .one]".to_string()));
+    assert!(parses.contains(&"[.one, .two]".to_string()));
+    assert!(parses.contains(&"[.two, .one]".to_string()));
+    assert_eq!(1, parser.parse_all("aa").skip(1).count());
+    assert_eq!(0, parser.parse_all("aab").count());
+}
+
+#[test]
+fn parse_all_json() {
+    let parser = quint::json::parser();
+    let parses: Vec<_> = parser.parse_all(r#"[1,{"a":null}]"#).collect();
+    assert_eq!(1, parses.len());
+    assert_eq!(parser.parse(r#"[1,{"a":null}]"#), parses[0]);
+
+    // The space can be consumed by the trivia after the ',' or by the trivia before the '1'.
+    let parses: Vec<_> = parser.parse_all(r#"[0, 1]"#).collect();
+    assert_eq!(2, parses.len());
+    assert_ne!(parses[0][0].children[1].span, parses[1][0].children[1].span);
+}
+
+#[test]
+fn generate_parallel() {
+    let node = all!("Boba", ' ', any!("Fett", "Jango"));
+    let generator = Generator::from(node.clone());
+    let parser = Parser::from(node);
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                let text = generator.generate().unwrap();
+                assert!(parser.try_parse(&text).is_ok());
+            });
+        }
+    });
+}
+
+#[test]
+fn indent_blocks() {
+    let node = all!(
+        &".item",
+        syntax(
+            ".item",
+            all!(
+                store('a'..='z'),
+                option(all!(
+                    ":\n",
+                    indent(),
+                    join(all!('\n', same_indent()), &".item"),
+                    dedent()
+                ))
+            )
+        ),
+    );
+    let text = "a:\n  b:\n    c\n  d:\n   e";
+    let trees = Parser::from(node.clone()).parse(text);
+    assert_eq!(
+        r#"[.item("a"): { .item("b"): { .item("c") }, .item("d"): { .item("e") } }]"#,
+        format!("{:?}", trees)
+    );
+    let memoized = Parser::from(node.clone()).memoize(1024).parse(text);
+    assert_eq!(format!("{:?}", trees), format!("{:?}", memoized));
+    let parser = Parser::from(node);
+    assert!(parser.try_parse("a:\nb").is_err());
+    assert!(parser.try_parse("a:\n  b\n c").is_err());
+    assert!(parser.try_parse("a\n  b").is_err());
+}
+
+fn counted(symbol: char, slot: &str) -> Node {
+    repeat(.., all!(symbol, set(slot, Set::Add(1))))
+}
+
+#[test]
+fn counted_abc() {
+    let node = all!(
+        counted('a', ".a"),
+        counted('b', ".b"),
+        check(".a", If::Equal, ".b"),
+        counted('c', ".c"),
+        check(".b", If::Equal, ".c"),
+    );
+    test("aabbcc", node.clone(), true);
+    test("", node.clone(), true);
+    test("aabbc", node.clone(), false);
+    test("abbcc", node.clone(), false);
+
+    let generator = Generator::from(node.clone());
+    let parser = Parser::from(node);
+    for _ in 0..16 {
+        let text = generator.generate().unwrap();
+        assert!(parser.try_parse(&text).is_ok(), "{}", text);
+    }
+}
+
+#[test]
+fn check_compare() {
+    let node = |compare| {
+        all!(
+            counted('a', ".a"),
+            '|',
+            counted('b', ".b"),
+            check(".a", compare, ".b")
+        )
+    };
+    for (compare, results) in [
+        (If::Less, [true, false, false]),
+        (If::LessEqual, [true, true, false]),
+        (If::Equal, [false, true, false]),
+        (If::NotEqual, [true, false, true]),
+        (If::Greater, [false, false, true]),
+        (If::GreaterEqual, [false, true, true]),
+    ] {
+        for (text, success) in ["a|bb", "aa|bb", "aaa|bb"].iter().zip(results.iter()) {
+            test(text, node(compare), *success);
+        }
+    }
+}
+
+#[test]
+fn set_arithmetic() {
+    fn square(value: isize) -> isize {
+        value * value
+    }
+    // The number of 'b' is '(a * 3 - c)^2' where 'c' is counted first.
+    let node = all!(
+        counted('c', ".c"),
+        '|',
+        counted('a', ".a"),
+        set(".x", Set::Copy(Identifier::Path(".a".into()))),
+        set(".x", Set::Multiply(3)),
+        set(".x", Set::Minus(Identifier::Path(".c".into()))),
+        set(".x", Set::Map(square)),
+        '|',
+        counted('b', ".b"),
+        check(".b", If::Equal, ".x"),
+    );
+    test("c|a|bbbb", node.clone(), true);
+    test("cc|aa|bbbbbbbbbbbbbbbb", node.clone(), true);
+    test("c|a|bbb", node.clone(), false);
+
+    let node = all!(
+        set(".x", Set::Value(2)),
+        set(".x", Set::Plus(Identifier::Path(".x".into()))),
+        set(".x", Set::Add(-1)),
+        counted('b', ".b"),
+        check(".b", If::Equal, ".x"),
+    );
+    test("bbb", node.clone(), true);
+    test("bbbb", node.clone(), false);
+}
+
+#[test]
+fn scope_restore() {
+    let node = all!(
+        set(".one", Set::Value(1)),
+        set(".x", Set::Value(1)),
+        scope(&[".x", ".one"], all!(set(".x", Set::Value(2)), 'a')),
+        check(".x", If::Equal, ".one"),
+        'b',
+    );
+    test("ab", node.clone(), true);
+    // A failed 'Pop' leaves the slots unchanged for the alternatives.
+    let node = all!(
+        set(".one", Set::Value(1)),
+        any!(
+            all!(scope(&[".x"], set(".x", Set::Value(1))), "a!"),
+            all!(check(".x", If::Less, ".one"), 'a')
+        ),
+    );
+    test("a", node.clone(), true);
+    test("a!", node, true);
+}
+
+fn element() -> Node {
+    all!(
+        &".element",
+        syntax(
+            ".element",
+            all!(
+                '<',
+                capture("tag", repeat(1.., 'a'..='z')),
+                '>',
+                repeat(.., &".element"),
+                "</",
+                back("tag"),
+                '>'
+            )
+        ),
+    )
+}
+
+#[test]
+fn back_element() {
+    let parser = Parser::from(element());
+    let trees = parser.parse("<boba><fett></fett><jango><karl></karl></jango></boba>");
+    assert_eq!(
+        r#"[.element("boba"): { .element("fett"), .element("jango"): { .element("karl") } }]"#,
+        format!("{:?}", trees)
+    );
+    assert!(parser.try_parse("<boba><fett></boba></fett>").is_err());
+    assert!(parser.try_parse("<boba><fett></fett></fett>").is_err());
+    let error = parser.try_parse("<boba></bob>").unwrap_err();
+    assert_eq!(8, error.index);
+    assert!(error.expected.contains(&Expect::Text("boba".into())));
+
+    let text = "<a><b><c></c></b><b></b></a>";
+    let memoized = Parser::from(element()).memoize(1024).parse(text);
+    assert_eq!(
+        format!("{:?}", parser.parse(text)),
+        format!("{:?}", memoized)
+    );
+}
+
+#[test]
+fn back_raw_string() {
+    let node = all!(
+        'r',
+        capture("hashes", repeat(.., '#')),
+        '"',
+        repeat(.., all!(not(all!('"', back("hashes"))), none_of([]))),
+        '"',
+        back("hashes")
+    );
+    test(r#"r"boba""#, node.clone(), true);
+    test(r###"r##"boba"#fett"##"###, node.clone(), true);
+    test(r###"r##"boba"#"###, node.clone(), false);
+    test(r###"r#"boba"##"###, node.clone(), false);
+}
+
+#[test]
+fn back_scope() {
+    // A capture goes out of scope at the end of its syntax.
+    let node = all!(
+        &".boba",
+        &".fett",
+        syntax(".boba", capture("x", 'a'..='z')),
+        syntax(".fett", back("x")),
+    );
+    test("aa", node, false);
+    // A capture is visible in the syntaxes nested in its scope.
+    let node = all!(
+        capture("x", 'a'..='z'),
+        &".fett",
+        syntax(".fett", all!(back("x"), capture("x", 'a'..='z'), back("x"))),
+        back("x"),
+    );
+    test("aabba", node.clone(), true);
+    test("aabbb", node, false);
+}
+
+#[test]
+fn generate_back() {
+    let generator = Generator::from(element());
+    let parser = Parser::from(element());
+    for _ in 0..32 {
+        let text = generator.generate().unwrap();
+        assert!(parser.try_parse(&text).is_ok(), "{}", text);
+    }
+}
+
+fn netstring() -> Node {
+    all!(integer(".n", 10), ':', repeat_value(".n", none_of([])), ',')
+}
+
+#[test]
+fn integer_netstring() {
+    test("5:hello,", netstring(), true);
+    test("0:,", netstring(), true);
+    test("12:hello, world,", netstring(), true);
+    test("5:hell,", netstring(), false);
+    test("5:hello!,", netstring(), false);
+    test(":hello,", netstring(), false);
+    // A long repetition loops instead of recursing.
+    let text = format!("100000:{},", "a".repeat(100000));
+    test(&text, netstring(), true);
+    // An integer that overflows fails instead of saturating.
+    test("99999999999999999999:a,", netstring(), false);
+    test("9223372036854775807:", all!(integer(".n", 10), ':'), true);
+    test("9223372036854775808:", all!(integer(".n", 10), ':'), false);
+    // Repetitions nest such that the outer count is restored after the inner one.
+    let node = all!(
+        integer(".n", 16),
+        repeat_value(
+            ".n",
+            all!('(', integer(".n", 16), repeat_value(".n", 'x'), ')')
+        ),
+    );
+    test("2(1x)(ax)", node.clone(), false);
+    test("2(1x)(axxxxxxxxxx)", node.clone(), true);
+    test("2(1x)(Axxxxxxxxxx)", node, true);
+}
+
+#[test]
+fn generate_netstring() {
+    let generator = Generator::from(netstring());
+    let parser = Parser::from(netstring());
+    let memoized = parser.clone().memoize(1 << 8);
+    for _ in 0..32 {
+        let text = generator.generate().unwrap();
+        assert!(parser.try_parse(&text).is_ok(), "{}", text);
+        assert!(memoized.try_parse(&text).is_ok(), "{}", text);
+    }
+}
+
+fn identifier() -> Node {
+    let keyword = native(&[], |text, _| {
+        let length = text
+            .find(|symbol: char| !symbol.is_ascii_alphanumeric())
+            .unwrap_or(text.len());
+        match &text[..length] {
+            "if" | "else" | "while" => None,
+            _ => Some(0),
+        }
+    });
+    all!(keyword, repeat(1.., any!('a'..='z', '0'..='9')))
+}
+
+#[test]
+fn native_keyword() {
+    test("boba", identifier(), true);
+    test("iffy", identifier(), true);
+    test("if", identifier(), false);
+    test("while", identifier(), false);
+}
+
+#[test]
+fn native_consume() {
+    let number = native(&[], |text, _| {
+        let length = text
+            .find(|symbol: char| !symbol.is_ascii_digit())
+            .unwrap_or(text.len());
+        text[..length].parse::<u32>().ok().map(|_| length)
+    });
+    let node = all!('[', number, ']');
+    test("[4294967295]", node.clone(), true);
+    test("[4294967296]", node.clone(), false);
+    test("[]", node.clone(), false);
+
+    // The prefix of a sampled candidate that the predicate consumes is generated.
+    let generator = Generator::from(node.clone());
+    let parser = Parser::from(node.clone());
+    for _ in 0..16 {
+        let text = generator.generate().unwrap();
+        assert!(parser.try_parse(&text).is_ok(), "{}", text);
+    }
+    // A length that does not end at a character boundary fails.
+    test("é", native(&[], |_, _| Some(1)), false);
+    test("é", native(&[], |_, _| Some(2)), true);
+    // A length that overflows the index fails.
+    test("ab", all!('a', native(&[], |_, _| Some(usize::MAX))), false);
+}
+
+#[test]
+fn native_slots() {
+    let node = all!(
+        counted('a', ".a"),
+        native(&[".a"], |_, values| (values[0] % 2 == 0).then_some(0)),
+        'b',
+    );
+    test("aab", node.clone(), true);
+    test("b", node.clone(), true);
+    test("ab", node.clone(), false);
+    test("aaab", node.clone(), false);
+
+    // Outputs that fail the predicate are generated again.
+    let generator = Generator::from(node.clone());
+    let parser = Parser::from(node);
+    for _ in 0..16 {
+        let text = generator.generate().unwrap();
+        assert!(parser.try_parse(&text).is_ok(), "{}", text);
+        assert_eq!(text.len() % 2, 1, "{}", text);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Byte(u8),
+    Word(String),
+    List(Vec<Item>),
+}
+
+fn items() -> Node {
+    let item = || any!(&".byte", &".word", &".list", &".group");
+    all!(
+        any!(all!(&".list", '!'), all!(&".list", '?')),
+        syntax(".list", all!('[', join(',', item()), ']')),
+        syntax(".group", all!('(', join(',', item()), ')')),
+        syntax(".byte", store(repeat(1.., '0'..='9'))),
+        syntax(".word", store(repeat(1.., any!('a'..='z', '0'..='9')))),
+    )
+}
+
+fn item_actions() -> Actions<Item> {
+    Actions::new()
+        .on(".byte", |reduced, _| {
+            Some(Item::Byte(reduced.values[0].parse().ok()?))
+        })
+        .on(".word", |reduced, _| {
+            Some(Item::Word(reduced.values[0].into()))
+        })
+        .on(".list", |_, children| Some(Item::List(children)))
+}
+
+#[test]
+fn actions_reduce() {
+    let parser = Parser::from(items());
+    let memoized = parser.clone().memoize(1 << 8);
+    let word = |word: &str| Item::Word(word.into());
+    let cases = [
+        ("[]!", vec![Item::List(vec![])]),
+        // A failed action backtracks to the next alternative.
+        (
+            "[1,300]?",
+            vec![Item::List(vec![Item::Byte(1), word("300")])],
+        ),
+        // A syntax without an action passes the values of its children to its parent.
+        (
+            "[a,(1,[b]),()]?",
+            vec![Item::List(vec![
+                word("a"),
+                Item::Byte(1),
+                Item::List(vec![word("b")]),
+            ])],
+        ),
+    ];
+    for (text, items) in cases {
+        assert_eq!(parser.reduce(text, &item_actions()), Ok(items.clone()));
+        assert_eq!(memoized.reduce(text, &item_actions()), Ok(items));
+    }
+    assert!(parser.reduce("[a,b]", &item_actions()).is_err());
+    assert!(parser.reduce("[a,b!", &item_actions()).is_err());
+}
+
+#[test]
+fn owned_trees() {
+    let parser = Parser::from(element());
+    let text = String::from("<a><b></b><c></c></a>");
+    let trees = parser.try_parse(&text).unwrap();
+    let owned: Vec<OwnedTree> = trees.iter().cloned().map(Tree::into_owned).collect();
+    let expected = format!("{:?}", trees);
+    drop(trees);
+    drop(text);
+    let owned = thread::spawn(move || owned).join().unwrap();
+    assert_eq!(format!("{:?}", owned), expected);
+    let text = "<a><b></b><c></c></a>";
+    let trees: Vec<_> = owned.iter().map(OwnedTree::as_tree).collect();
+    assert_eq!(trees, parser.try_parse(text).unwrap());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_trees() {
+    let parser = Parser::from(element());
+    let text = "<a><b></b><c></c></a>";
+    let trees = parser.try_parse(text).unwrap();
+    let json = serde_json::to_string(&trees).unwrap();
+    let owned: Vec<OwnedTree> = serde_json::from_str(&json).unwrap();
+    assert_eq!(json, serde_json::to_string(&owned).unwrap());
+    assert_eq!(
+        owned.iter().map(OwnedTree::as_tree).collect::<Vec<_>>(),
+        trees
+    );
+
+    // Values with escaped characters are owned when deserialized.
+    let parser = Parser::from(and(
+        &".word",
+        syntax(".word", store(repeat(1.., any!('a'..='z', '"', '\\')))),
+    ));
+    let trees = parser.try_parse("a\"b\\c").unwrap();
+    let json = serde_json::to_string(&trees).unwrap();
+    let owned: Vec<OwnedTree> = serde_json::from_str(&json).unwrap();
+    assert_eq!(owned[0].values, vec!["a\"b\\c"]);
+    assert_eq!(
+        owned.iter().map(OwnedTree::as_tree).collect::<Vec<_>>(),
+        trees
+    );
+}
+
+fn assignments() -> Node {
+    let name = || store_as("name", repeat(1.., 'a'..='z'));
+    all!(
+        repeat(.., &".assign"),
+        syntax(
+            ".assign",
+            all!(
+                name(),
+                repeat(.., all!(',', name())),
+                '=',
+                field("value", &".number"),
+                repeat(.., all!('+', field("value", &".number"))),
+                ';'
+            )
+        ),
+        syntax(".number", store_as("digits", repeat(1.., '0'..='9'))),
+    )
+}
+
+#[test]
+fn fields_and_labels() {
+    let parser = Parser::from(assignments());
+    let trees = parser.try_parse("a,bc=1+23;d=4;").unwrap();
+    assert_eq!(trees[0].value("name"), Some("a"));
+    assert_eq!(trees[0].value_all("name"), ["a", "bc"]);
+    let values: Vec<_> = trees[0]
+        .field_all("value")
+        .into_iter()
+        .map(|tree| tree.value("digits").unwrap())
+        .collect();
+    assert_eq!(values, ["1", "23"]);
+    assert_eq!(trees[1].field("value").unwrap().value("digits"), Some("4"));
+    assert_eq!(trees[1].field("name"), None);
+    assert_eq!(trees[1].value("digits"), None);
+}
+
+#[test]
+fn fields_reduce() {
+    // Reducers find the values and children of a syntax by name like conversions of its tree do.
+    let named = |names: &[(String, usize)], name: &str| -> Vec<usize> {
+        names
+            .iter()
+            .filter(|pair| pair.0 == name)
+            .map(|pair| pair.1)
+            .collect()
+    };
+    let actions = Actions::new()
+        .on(".number", move |reduced, _| {
+            let index = *named(&reduced.labels, "digits").first()?;
+            Some(reduced.values[index].to_string())
+        })
+        .on(".assign", move |reduced, children| {
+            let names: Vec<_> = named(&reduced.labels, "name")
+                .into_iter()
+                .map(|index| reduced.values[index])
+                .collect();
+            let values: Vec<_> = named(&reduced.fields, "value")
+                .into_iter()
+                .map(|index| children[index].clone())
+                .collect();
+            Some(format!("{}={}", names.join(","), values.join("+")))
+        });
+    let parser = Parser::from(assignments());
+    let memoized = parser.clone().memoize(1 << 8);
+    for parser in [parser, memoized] {
+        let assigns = parser.reduce("a,bc=1+23;d=4;", &actions).unwrap();
+        assert_eq!(assigns, ["a,bc=1+23", "d=4"]);
+    }
+}
+
+#[test]
+fn fields_without_trees() {
+    // A field only names a tree that its node parsed, not a tree that was parsed before it.
+    let node = all!(
+        &".pair",
+        syntax(".pair", all!(&".x", field("second", option(&".x")))),
+        syntax(".x", 'x'),
+    );
+    let parser = Parser::from(node);
+    let trees = parser.try_parse("x").unwrap();
+    assert!(trees[0].fields.is_empty());
+    let trees = parser.try_parse("xx").unwrap();
+    assert_eq!(trees[0].fields, [("second".to_string(), 1)]);
+}
+
+#[test]
+fn fields_backtrack() {
+    // The name of an alternative that fails is dropped with its tree.
+    let node = all!(
+        any!(
+            all!(field("left", &".x"), '<'),
+            all!(field("right", &".x"), '>')
+        ),
+        syntax(".x", 'x'),
+    );
+    let node = all!(&".pair", syntax(".pair", node));
+    let trees = Parser::from(node).try_parse("x>").unwrap();
+    assert_eq!(trees[0].fields, [("right".to_string(), 0)]);
+    assert!(trees[0].field("left").is_none());
+    // The names in a memoized reference are replayed relative to the stacks it is replayed on.
+    let node = all!(
+        &".pair",
+        syntax(
+            ".pair",
+            all!(
+                field("first", &".x"),
+                any!(all!(&"item", '<'), all!(&"item", '>'))
+            )
+        ),
+        define("item", all!(store_as("name", 'y'), field("second", &".x"))),
+        syntax(".x", 'x'),
+    );
+    let trees = Parser::from(node).try_parse("xyx>").unwrap();
+    let fields = [("first".to_string(), 0), ("second".to_string(), 1)];
+    assert_eq!(trees[0].fields, fields);
+    assert_eq!(trees[0].value("name"), Some("y"));
+}
+
+#[test]
+fn undo_failed_alternatives() {
+    // Each first alternative changes the state and then fails such that the second one must see the
+    // state as it was before the first one.
+    let parse = |node: Node, text: &str| {
+        let parser = Parser::from(node);
+        let trees = parser.try_parse(text).map(|trees| format!("{:?}", trees));
+        let memoized = parser.memoize(1024).try_parse(text);
+        assert_eq!(trees, memoized.map(|trees| format!("{:?}", trees)));
+        trees
+    };
+    let values = all!(
+        any!(
+            all!(set(".a", Set::Add(1)), check(".a", If::Equal, ".b")),
+            'y'
+        ),
+        check(".a", If::Equal, ".b")
+    );
+    assert!(parse(values, "y").is_ok());
+
+    // The alternatives share the start of their scopes such that the first one pops a save that was
+    // pushed before the choice.
+    let saves = all!(
+        any!(
+            all!(
+                scope(&[".a"], set(".a", Set::Value(1))),
+                check(".a", If::NotEqual, ".b")
+            ),
+            all!(scope(&[".a"], set(".a", Set::Value(2))), 'y')
+        ),
+        check(".a", If::Equal, ".b")
+    );
+    assert!(parse(saves, "y").is_ok());
+
+    // The alternatives share the start of their syntaxes such that the spawns adopt what was parsed
+    // before the choice.
+    let spawns = all!(
+        any!(all!(&".boba", check(".a", If::NotEqual, ".b")), &".fett"),
+        syntax(".boba", capture("name", 'a')),
+        syntax(".fett", all!(capture("name", 'a'), store(back("name")))),
+    );
+    assert_eq!(Ok(r#"[.fett("a", "a")]"#.into()), parse(spawns, "aa"));
+}
+
+#[test]
+fn identify() {
+    use Identifier::*;
+
+    // The definitions and the slots are indexed like 'resolve' does, but the nodes are not optimized.
+    let node = all!(
+        define(".a.b", "xy"),
+        refer(".a"),
+        set(".c", Set::Copy(Path(".d".into())))
+    );
+    let (node, nodes, rules, slots) = node.identify();
+    let index = rules[&Path(".a".into())];
+    assert_eq!(nodes[index], and(Node::Symbol('x'), Node::Symbol('y')));
+    let copy = Set::Copy(Index(slots[&Path(".d".into())]));
+    let set = Node::Set(Index(slots[&Path(".c".into())]), copy);
+    assert_eq!(node, and(Node::Refer(Index(index)), set));
+}
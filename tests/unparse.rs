@@ -0,0 +1,113 @@
+extern crate quint;
+use quint::json::Syntax;
+use quint::node::*;
+use quint::parse::*;
+use quint::unparse::*;
+use quint::*;
+
+/// Prints the trees parsed from 'text' and verifies that the printed text parses into the same trees.
+fn round_trip(node: Node, text: &str) -> String {
+    let parser = Parser::from(node.clone());
+    let trees = parser.try_parse(text).unwrap();
+    let printed = Unparser::from(node).print_all(&trees).unwrap();
+    let reparsed = parser.try_parse(&printed).unwrap();
+    assert_eq!(format!("{:?}", trees), format!("{:?}", reparsed));
+    printed
+}
+
+#[test]
+fn json_minimal() {
+    let node = and(&"", json::node());
+    let printed = round_trip(node, " { \"a\" : [1, true, \"b\\n\"] ,\n \"c\": {} }\n");
+    assert_eq!(printed, "{\"a\":[1,true,\"b\\n\"],\"c\":{}}");
+}
+
+#[test]
+fn json_edit() {
+    let mut syntax = json::parse("{ \"a\": [1, true] }").unwrap();
+    if let Syntax::Object(pairs) = &mut syntax {
+        pairs[0].1 = Syntax::Array(vec![Syntax::Number(-2.5), Syntax::Null]);
+        pairs.push((Syntax::String("b".into()), Syntax::Boolean(false)));
+    }
+    let printed = json::print(&syntax).unwrap();
+    assert_eq!(printed, "{\"a\":[-2.5,null],\"b\":false}");
+    assert_eq!(json::parse(&printed), Some(syntax));
+    assert_eq!(json::print(&Syntax::Number(f64::NAN)), None);
+}
+
+#[test]
+fn json_generated() {
+    let generator = json::generator();
+    for _ in 0..32 {
+        if let Some(text) = generator.generate() {
+            round_trip(and(&"", json::node()), &text);
+        }
+    }
+}
+
+#[test]
+fn back_element() {
+    let node = all!(
+        &".element",
+        syntax(
+            ".element",
+            all!(
+                '<',
+                capture("tag", repeat(1.., 'a'..='z')),
+                '>',
+                repeat(.., &".element"),
+                "</",
+                back("tag"),
+                '>'
+            )
+        ),
+    );
+    let text = "<boba><fett></fett><jango><karl></karl></jango></boba>";
+    assert_eq!(round_trip(node, text), text);
+}
+
+#[test]
+fn invalid() {
+    let unparser = json::unparser();
+    let tree = Tree {
+        kind: ".boba".into(),
+        ..Tree::default()
+    };
+    assert_eq!(unparser.print(&tree), None);
+    let tree = Tree {
+        kind: ".null".into(),
+        values: vec!["fett"],
+        ..Tree::default()
+    };
+    assert_eq!(unparser.print(&tree), None);
+}
+
+#[test]
+fn script_indentation() {
+    let text = "\nif a:\n    b\n\n    while c:\n\t    d\n    e\nf\n";
+    let trees = script::parser().try_parse(text).unwrap();
+    let printed = script::unparser().print_all(&trees).unwrap();
+    assert_eq!(printed, "if a:\n b\n while c:\n  d\n e\nf");
+    let reparsed = script::parser().try_parse(&printed).unwrap();
+    assert_eq!(format!("{:?}", reparsed), format!("{:?}", trees));
+}
+
+#[test]
+fn json_large() {
+    // The printer keeps its pending nodes and choices on the heap, and closing a tree is progress such
+    // that nested trees do not exhaust the references entered without progress.
+    let array = Syntax::Array((0..600).map(|index| Syntax::Number(index as f64)).collect());
+    let printed = json::print(&array).unwrap();
+    assert_eq!(printed.len(), 2291);
+    assert_eq!(json::parse(&printed), Some(array));
+    let mut object = Syntax::Null;
+    for _ in 0..200 {
+        object = Syntax::Object(vec![(Syntax::String("a".into()), object)]);
+    }
+    let printed = json::print(&object).unwrap();
+    assert_eq!(
+        printed,
+        format!("{}null{}", "{\"a\":".repeat(200), "}".repeat(200))
+    );
+    assert_eq!(json::parse(&printed), Some(object));
+}
@@ -0,0 +1,51 @@
+#!/usr/bin/perl
+# Generates 'src/class/table.rs' from the Unicode Character Database that Perl bundles:
+#     perl tools/ucd.pl > src/class/table.rs
+# The header records the Unicode version and the hashes of the Perl tables that were read, such that a
+# regenerated table can be traced to its data. The ignored test 'class_table' verifies that the table is
+# up to date with this script.
+use strict;
+use warnings;
+use Config;
+use Digest::SHA;
+use Unicode::UCD qw(prop_invlist);
+
+my @properties = (
+    ["XID_START", "XID_Start"],
+    ["XID_CONTINUE", "XID_Continue"],
+    ["ALPHABETIC", "Alphabetic"],
+    ["WHITE_SPACE", "White_Space"],
+);
+my $output = "";
+for my $property (@properties) {
+    my ($name, $property) = @$property;
+    my @list = prop_invlist($property);
+    my @ranges;
+    for (my $i = 0; $i < @list; $i += 2) {
+        my $low = $list[$i];
+        my $high = ($i + 1 < @list ? $list[$i + 1] : 0x110000) - 1;
+        # Surrogates are not characters.
+        if ($low <= 0xDFFF && $high >= 0xD800) {
+            push @ranges, [$low, 0xD7FF] if $low < 0xD800;
+            push @ranges, [0xE000, $high] if $high > 0xDFFF;
+        } else {
+            push @ranges, [$low, $high];
+        }
+    }
+    $output .= "\n/// Ranges of the '$property' property.\n#[rustfmt::skip]\npub const $name: &[(char, char)] = &[\n";
+    for (my $i = 0; $i < @ranges; $i += 4) {
+        my $last = $i + 3 < $#ranges ? $i + 3 : $#ranges;
+        my @row = map { sprintf("('\\u{%x}', '\\u{%x}')", @$_) } @ranges[$i .. $last];
+        $output .= "    " . join(", ", @row) . ",\n";
+    }
+    $output .= "];\n";
+}
+
+print "// Generated by 'tools/ucd.pl' from the Unicode Character Database ", Unicode::UCD::UnicodeVersion(),
+    " of Perl $Config{version}.\n";
+print "// SHA-256 of the Perl tables that were read:\n";
+for my $file (sort grep { m{^unicore/lib/} } keys %INC) {
+    my $digest = Digest::SHA->new(256)->addfile($INC{$file})->hexdigest;
+    print "// - $file: $digest\n";
+}
+print $output;